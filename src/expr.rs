@@ -1,80 +1,823 @@
-use std::any::Any;
+use std::fmt;
+use std::ops;
+use crate::lox_parser::Precedence;
 use crate::token::Token;
 
+// Stable handle of a node in an Ast, usable as a key for side tables
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Default)]
+pub struct ExprId(usize);
+
+// Arena holding every node of a parsed expression; children are referenced by id
+// and are always added before their parent
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Ast {
+    nodes: Vec<ExprEnum>,
+    pub(crate) root: ExprId,
+}
+
+impl Ast {
+    pub fn root(&self) -> ExprId {
+        self.root
+    }
+
+    pub fn add(&mut self, expr: ExprEnum) -> ExprId {
+        self.nodes.push(expr);
+        ExprId(self.nodes.len() - 1)
+    }
+
+    pub fn accept<T>(&self, id: ExprId, visitor: &mut dyn Visitor<T>) -> T {
+        match &self[id] {
+            ExprEnum::Binary(expr) => visitor.visit_binary(self, id, expr),
+            ExprEnum::Grouping(expr) => visitor.visit_grouping(self, id, expr),
+            ExprEnum::Literal(expr) => visitor.visit_literal(self, id, expr),
+            ExprEnum::Unary(expr) => visitor.visit_unary(self, id, expr),
+            ExprEnum::Variable(expr) => visitor.visit_variable(self, id, expr),
+            ExprEnum::Call(expr) => visitor.visit_call(self, id, expr),
+            ExprEnum::Get(expr) => visitor.visit_get(self, id, expr),
+            ExprEnum::List(expr) => visitor.visit_list(self, id, expr),
+            ExprEnum::Index(expr) => visitor.visit_index(self, id, expr),
+            ExprEnum::Slice(expr) => visitor.visit_slice(self, id, expr),
+            ExprEnum::SetIndex(expr) => visitor.visit_set_index(self, id, expr),
+            ExprEnum::None => panic!("Invalid expression type"),
+        }
+    }
+
+    // Hands every node under `id` to the walker, parents before their children and children
+    // left to right. The walk keeps its own stack, so it is safe on trees of any depth
+    pub fn walk(&self, id: ExprId, walker: &mut dyn Walker) {
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            match &self[id] {
+                ExprEnum::Binary(expr) => walker.visit_binary(self, id, expr),
+                ExprEnum::Grouping(expr) => walker.visit_grouping(self, id, expr),
+                ExprEnum::Literal(expr) => walker.visit_literal(self, id, expr),
+                ExprEnum::Unary(expr) => walker.visit_unary(self, id, expr),
+                ExprEnum::Variable(expr) => walker.visit_variable(self, id, expr),
+                ExprEnum::Call(expr) => walker.visit_call(self, id, expr),
+                ExprEnum::Get(expr) => walker.visit_get(self, id, expr),
+                ExprEnum::List(expr) => walker.visit_list(self, id, expr),
+                ExprEnum::Index(expr) => walker.visit_index(self, id, expr),
+                ExprEnum::Slice(expr) => walker.visit_slice(self, id, expr),
+                ExprEnum::SetIndex(expr) => walker.visit_set_index(self, id, expr),
+                ExprEnum::None => panic!("Invalid expression type"),
+            }
+            let first = pending.len();
+            self[id].clone().map_children(|child| {
+                pending.push(child);
+                child
+            });
+            pending[first..].reverse();
+        }
+    }
+
+    // Hands every node reachable from the root to the folder by value, children first,
+    // and collects what it returns into a new arena. Nodes the folder drops stay behind
+    // as unreachable entries, and are not folded again by a later pass
+    pub fn fold(self, folder: &mut dyn Folder) -> Ast {
+        // A child is always added before its parent, so one pass from the end finds them all
+        let mut reachable = vec![false; self.nodes.len()];
+        reachable[self.root.0] = true;
+        for (id, node) in self.nodes.iter().enumerate().rev() {
+            if reachable[id] {
+                node.clone().map_children(|child| {
+                    reachable[child.0] = true;
+                    child
+                });
+            }
+        }
+
+        let mut folded = Ast::default();
+        let mut ids: Vec<ExprId> = Vec::with_capacity(self.nodes.len());
+        for (node, reachable) in self.nodes.into_iter().zip(reachable) {
+            if !reachable {
+                // Never looked up, as no reachable node refers to it
+                ids.push(ExprId(usize::MAX));
+                continue;
+            }
+            let id = match node.map_children(|child| ids[child.0]) {
+                ExprEnum::Binary(expr) => folder.fold_binary(&mut folded, expr),
+                ExprEnum::Grouping(expr) => folder.fold_grouping(&mut folded, expr),
+                ExprEnum::Literal(expr) => folder.fold_literal(&mut folded, expr),
+                ExprEnum::Unary(expr) => folder.fold_unary(&mut folded, expr),
+                ExprEnum::Variable(expr) => folder.fold_variable(&mut folded, expr),
+                ExprEnum::Call(expr) => folder.fold_call(&mut folded, expr),
+                ExprEnum::Get(expr) => folder.fold_get(&mut folded, expr),
+                ExprEnum::List(expr) => folder.fold_list(&mut folded, expr),
+                ExprEnum::Index(expr) => folder.fold_index(&mut folded, expr),
+                ExprEnum::Slice(expr) => folder.fold_slice(&mut folded, expr),
+                ExprEnum::SetIndex(expr) => folder.fold_set_index(&mut folded, expr),
+                ExprEnum::None => folded.add(ExprEnum::None),
+            };
+            ids.push(id);
+        }
+        folded.root = ids[self.root.0];
+        folded
+    }
+
+    // Line of the leftmost token of a node, found without recursing so it is safe on
+    // trees too deep to visit
+    pub(crate) fn line(&self, mut id: ExprId) -> usize {
+        loop {
+            match &self[id] {
+                ExprEnum::Binary(expr) => id = expr.left,
+                ExprEnum::Grouping(expr) => id = expr.expression,
+                ExprEnum::Call(expr) => id = expr.callee,
+                ExprEnum::Get(expr) => id = expr.object,
+                ExprEnum::Index(expr) => id = expr.object,
+                ExprEnum::Slice(expr) => id = expr.object,
+                ExprEnum::SetIndex(expr) => id = expr.object,
+                ExprEnum::List(expr) => return expr.bracket.line,
+                ExprEnum::Literal(expr) => return expr.line,
+                ExprEnum::Unary(expr) => return expr.op.line,
+                ExprEnum::Variable(expr) => return expr.name.line,
+                ExprEnum::None => return 0,
+            }
+        }
+    }
+
+    // The binary nodes down the left side of a chain like `1 + 2 - 3`, innermost first,
+    // and the operand the chain starts from. Walking a chain through this takes no stack
+    // frame per operator, so long flat sums are as safe to visit as short ones
+    pub fn chain(&self, mut id: ExprId) -> (ExprId, Vec<(ExprId, &Binary)>) {
+        let mut chain = Vec::new();
+        while let ExprEnum::Binary(expr) = &self[id] {
+            chain.push((id, expr));
+            id = expr.left;
+        }
+        chain.reverse();
+        (id, chain)
+    }
+
+    // First and last source line covered by a node
+    fn span(&self, id: ExprId) -> (usize, usize) {
+        match &self[id] {
+            ExprEnum::Binary(expr) => {
+                let (first, chain) = self.chain(id);
+                let start = chain.iter().fold(self.span(first).0, |start, (_, expr)| start.min(expr.op.line));
+                (start, self.span(expr.right).1.max(expr.op.line))
+            }
+            ExprEnum::Grouping(expr) => self.span(expr.expression),
+            ExprEnum::Literal(expr) => (expr.line, expr.line),
+            ExprEnum::Unary(expr) => (expr.op.line, self.span(expr.right).1),
+            ExprEnum::Variable(expr) => (expr.name.line, expr.name.line),
+            ExprEnum::Call(expr) => (self.span(expr.callee).0, expr.paren.line),
+            ExprEnum::Get(expr) => (self.span(expr.object).0, expr.name.line),
+            ExprEnum::List(expr) => (expr.bracket.line, expr.end),
+            ExprEnum::Index(expr) => (self.span(expr.object).0, expr.end),
+            ExprEnum::Slice(expr) => (self.span(expr.object).0, expr.end),
+            ExprEnum::SetIndex(expr) => (self.span(expr.object).0, self.span(expr.value).1),
+            ExprEnum::None => panic!("Invalid expression type"),
+        }
+    }
+}
+
+impl ops::Index<ExprId> for Ast {
+    type Output = ExprEnum;
+
+    fn index(&self, id: ExprId) -> &ExprEnum {
+        &self.nodes[id.0]
+    }
+}
+
 // Define the enum with variants for each type
+#[derive(Debug, PartialEq, Clone)]
 pub enum ExprEnum {
     Binary(Binary),
     Grouping(Grouping),
     Literal(Literal),
     Unary(Unary),
+    Variable(Variable),
+    Call(Call),
+    Get(Get),
+    List(List),
+    Index(Index),
+    Slice(Slice),
+    SetIndex(SetIndex),
     None,
 }
 
-// Implement the Expr trait for the enum
 impl ExprEnum {
-    pub(crate) fn accept<T>(&self, visitor: &dyn Visitor<T>) -> T {
+    fn map_children(self, mut f: impl FnMut(ExprId) -> ExprId) -> ExprEnum {
         match self {
-            ExprEnum::Binary(expr) => visitor.visit_binary(expr),
-            ExprEnum::Grouping(expr) => visitor.visit_grouping(expr),
-            ExprEnum::Literal(expr) => visitor.visit_literal(expr),
-            ExprEnum::Unary(expr) => visitor.visit_unary(expr),
-            ExprEnum::None => panic!("Invalid expression type"),
+            ExprEnum::Binary(expr) => ExprEnum::Binary(Binary {
+                left: f(expr.left),
+                op: expr.op,
+                right: f(expr.right),
+            }),
+            ExprEnum::Grouping(expr) => ExprEnum::Grouping(Grouping {
+                expression: f(expr.expression),
+            }),
+            ExprEnum::Unary(expr) => ExprEnum::Unary(Unary {
+                op: expr.op,
+                right: f(expr.right),
+            }),
+            ExprEnum::Call(expr) => ExprEnum::Call(Call {
+                callee: f(expr.callee),
+                paren: expr.paren,
+                arguments: expr.arguments.into_iter().map(f).collect(),
+            }),
+            ExprEnum::Get(expr) => ExprEnum::Get(Get {
+                object: f(expr.object),
+                name: expr.name,
+            }),
+            ExprEnum::List(expr) => ExprEnum::List(List {
+                bracket: expr.bracket,
+                elements: expr.elements.into_iter().map(&mut f).collect(),
+                end: expr.end,
+            }),
+            ExprEnum::Index(expr) => ExprEnum::Index(Index {
+                object: f(expr.object),
+                bracket: expr.bracket,
+                index: f(expr.index),
+                end: expr.end,
+            }),
+            ExprEnum::Slice(expr) => ExprEnum::Slice(Slice {
+                object: f(expr.object),
+                bracket: expr.bracket,
+                start: expr.start.map(&mut f),
+                stop: expr.stop.map(&mut f),
+                end: expr.end,
+            }),
+            ExprEnum::SetIndex(expr) => ExprEnum::SetIndex(SetIndex {
+                object: f(expr.object),
+                bracket: expr.bracket,
+                index: f(expr.index),
+                value: f(expr.value),
+            }),
+            other => other,
         }
     }
+
+    // How tightly this node binds when printed back as source
+    fn precedence(&self) -> Precedence {
+        match self {
+            ExprEnum::Binary(expr) => Precedence::of_binary(&expr.op.token_type),
+            ExprEnum::Unary(_) => Precedence::Unary,
+            ExprEnum::SetIndex(_) => Precedence::Assignment,
+            _ => Precedence::Primary,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binary {
+    pub left: ExprId,
+    pub op: Token,
+    pub right: ExprId,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Literal {
+    pub value: LiteralValue,
+    pub line: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LiteralValue {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+impl fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LiteralValue::Number(n) => write!(f, "{:?}", n),
+            LiteralValue::String(s) => write!(f, "{}", s),
+            LiteralValue::Bool(b) => write!(f, "{}", b),
+            LiteralValue::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Unary {
+    pub op: Token,
+    pub right: ExprId,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Grouping {
+    pub expression: ExprId,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Variable {
+    pub name: Token,
+}
+
+// `paren` is the closing parenthesis, whose line is reported for errors in the call
+#[derive(Debug, PartialEq, Clone)]
+pub struct Call {
+    pub callee: ExprId,
+    pub paren: Token,
+    pub arguments: Vec<ExprId>,
+}
+
+// Property access `object.name`; a method call is a Call whose callee is a Get
+#[derive(Debug, PartialEq, Clone)]
+pub struct Get {
+    pub object: ExprId,
+    pub name: Token,
 }
 
-pub(crate) struct Binary {
-    pub(crate) left: Box<ExprEnum>,
-    pub(crate) op: Token,
-    pub(crate) right: Box<ExprEnum>,
+// `bracket` is the opening `[`, reported for errors. `end` is the line of the closing `]`
+#[derive(Debug, PartialEq, Clone)]
+pub struct List {
+    pub bracket: Token,
+    pub elements: Vec<ExprId>,
+    pub end: usize,
 }
 
-pub(crate) struct Literal {
-    pub(crate) value: Box<dyn Any>,
+// `object[index]`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Index {
+    pub object: ExprId,
+    pub bracket: Token,
+    pub index: ExprId,
+    pub end: usize,
 }
 
-pub(crate) struct Unary {
-    pub(crate) op: Token,
-    pub(crate) right: Box<ExprEnum>,
+// `object[start:stop]`, where either bound may be left out
+#[derive(Debug, PartialEq, Clone)]
+pub struct Slice {
+    pub object: ExprId,
+    pub bracket: Token,
+    pub start: Option<ExprId>,
+    pub stop: Option<ExprId>,
+    pub end: usize,
 }
 
-pub(crate) struct Grouping {
-    pub(crate) expression: Box<ExprEnum>,
+// `object[index] = value`
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetIndex {
+    pub object: ExprId,
+    pub bracket: Token,
+    pub index: ExprId,
+    pub value: ExprId,
 }
 
-// Update the Visitor trait to accept specific types instead of dyn Expr
+// Each visit gets the arena to follow child ids and the id of the visited node
 pub trait Visitor<T> {
-    fn visit_binary(&self, expr: &Binary) -> T;
-    fn visit_literal(&self, expr: &Literal) -> T;
-    fn visit_grouping(&self, expr: &Grouping) -> T;
-    fn visit_unary(&self, expr: &Unary) -> T;
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, expr: &Binary) -> T;
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, expr: &Literal) -> T;
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) -> T;
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, expr: &Unary) -> T;
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> T;
+    fn visit_call(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> T;
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, expr: &Get) -> T;
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, expr: &List) -> T;
+    fn visit_index(&mut self, ast: &Ast, id: ExprId, expr: &Index) -> T;
+    fn visit_slice(&mut self, ast: &Ast, id: ExprId, expr: &Slice) -> T;
+    fn visit_set_index(&mut self, ast: &Ast, id: ExprId, expr: &SetIndex) -> T;
+}
+
+// Looks at the nodes of a tree through Ast::walk, which does the traversal. The defaults
+// do nothing, so a pass that only collects or checks something overrides just the kinds
+// it cares about
+pub trait Walker {
+    fn visit_binary(&mut self, _ast: &Ast, _id: ExprId, _expr: &Binary) {}
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, _expr: &Literal) {}
+    fn visit_grouping(&mut self, _ast: &Ast, _id: ExprId, _expr: &Grouping) {}
+    fn visit_unary(&mut self, _ast: &Ast, _id: ExprId, _expr: &Unary) {}
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, _expr: &Variable) {}
+    fn visit_call(&mut self, _ast: &Ast, _id: ExprId, _expr: &Call) {}
+    fn visit_get(&mut self, _ast: &Ast, _id: ExprId, _expr: &Get) {}
+    fn visit_list(&mut self, _ast: &Ast, _id: ExprId, _expr: &List) {}
+    fn visit_index(&mut self, _ast: &Ast, _id: ExprId, _expr: &Index) {}
+    fn visit_slice(&mut self, _ast: &Ast, _id: ExprId, _expr: &Slice) {}
+    fn visit_set_index(&mut self, _ast: &Ast, _id: ExprId, _expr: &SetIndex) {}
+}
+
+// Rewrites a tree through Ast::fold. Each node arrives after its children were folded,
+// with child ids already pointing into the new arena `ast`; the defaults keep the node
+// unchanged, so a pass only overrides the kinds it rewrites
+pub trait Folder {
+    fn fold_binary(&mut self, ast: &mut Ast, expr: Binary) -> ExprId {
+        ast.add(ExprEnum::Binary(expr))
+    }
+
+    fn fold_literal(&mut self, ast: &mut Ast, expr: Literal) -> ExprId {
+        ast.add(ExprEnum::Literal(expr))
+    }
+
+    fn fold_grouping(&mut self, ast: &mut Ast, expr: Grouping) -> ExprId {
+        ast.add(ExprEnum::Grouping(expr))
+    }
+
+    fn fold_unary(&mut self, ast: &mut Ast, expr: Unary) -> ExprId {
+        ast.add(ExprEnum::Unary(expr))
+    }
+
+    fn fold_variable(&mut self, ast: &mut Ast, expr: Variable) -> ExprId {
+        ast.add(ExprEnum::Variable(expr))
+    }
+
+    fn fold_call(&mut self, ast: &mut Ast, expr: Call) -> ExprId {
+        ast.add(ExprEnum::Call(expr))
+    }
+
+    fn fold_get(&mut self, ast: &mut Ast, expr: Get) -> ExprId {
+        ast.add(ExprEnum::Get(expr))
+    }
+
+    fn fold_list(&mut self, ast: &mut Ast, expr: List) -> ExprId {
+        ast.add(ExprEnum::List(expr))
+    }
+
+    fn fold_index(&mut self, ast: &mut Ast, expr: Index) -> ExprId {
+        ast.add(ExprEnum::Index(expr))
+    }
+
+    fn fold_slice(&mut self, ast: &mut Ast, expr: Slice) -> ExprId {
+        ast.add(ExprEnum::Slice(expr))
+    }
+
+    fn fold_set_index(&mut self, ast: &mut Ast, expr: SetIndex) -> ExprId {
+        ast.add(ExprEnum::SetIndex(expr))
+    }
 }
 
 pub struct AstPrinter;
 
 impl Visitor<String> for AstPrinter {
-    fn visit_binary(&self, expr: &Binary) -> String {
-        format!("({} {} {})", expr.op.lexeme, expr.left.accept(self), expr.right.accept(self))
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, _expr: &Binary) -> String {
+        let (first, chain) = ast.chain(id);
+        let mut source: String = chain.iter().rev().map(|(_, expr)| format!("({} ", expr.op.lexeme)).collect();
+        source.push_str(&ast.accept(first, self));
+        for (_, expr) in &chain {
+            source.push_str(&format!(" {})", ast.accept(expr.right, self)));
+        }
+        source
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> String {
+        expr.value.to_string()
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> String {
+        format!("(group {})", ast.accept(expr.expression, self))
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> String {
+        format!("({} {})", expr.op.lexeme, ast.accept(expr.right, self))
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> String {
+        let mut parts = vec!["call".to_string(), ast.accept(expr.callee, self)];
+        parts.extend(expr.arguments.iter().map(|argument| ast.accept(*argument, self)));
+        format!("({})", parts.join(" "))
+    }
+
+    fn visit_get(&mut self, ast: &Ast, _id: ExprId, expr: &Get) -> String {
+        format!("(. {} {})", ast.accept(expr.object, self), expr.name.lexeme)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> String {
+        let mut parts = vec!["list".to_string()];
+        parts.extend(expr.elements.iter().map(|element| ast.accept(*element, self)));
+        format!("({})", parts.join(" "))
     }
 
-    fn visit_literal(&self, expr: &Literal) -> String {
-        if let Some(v) = expr.value.downcast_ref::<&str>() {
-            v.to_string()
-        } else if let Some(v) = expr.value.downcast_ref::<String>() {
-            v.clone()
-        } else if let Some(v) = expr.value.downcast_ref::<bool>() {
-            v.to_string()
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> String {
+        format!("([] {} {})", ast.accept(expr.object, self), ast.accept(expr.index, self))
+    }
+
+    fn visit_slice(&mut self, ast: &Ast, _id: ExprId, expr: &Slice) -> String {
+        let mut bound = |bound: Option<ExprId>| bound.map_or("nil".to_string(), |id| ast.accept(id, self));
+        let (start, stop) = (bound(expr.start), bound(expr.stop));
+        format!("([:] {} {} {})", ast.accept(expr.object, self), start, stop)
+    }
+
+    fn visit_set_index(&mut self, ast: &Ast, _id: ExprId, expr: &SetIndex) -> String {
+        format!(
+            "([]= {} {} {})",
+            ast.accept(expr.object, self),
+            ast.accept(expr.index, self),
+            ast.accept(expr.value, self)
+        )
+    }
+}
+
+// Prints the tree back as Lox source, adding parentheses only where the
+// precedence or left associativity of the parent would otherwise regroup a child
+pub struct InfixPrinter;
+
+impl InfixPrinter {
+    fn operand(&mut self, ast: &Ast, id: ExprId, min: Precedence) -> String {
+        let source = ast.accept(id, self);
+        if ast[id].precedence() < min {
+            format!("({})", source)
         } else {
-            panic!("Unsupported type")
+            source
+        }
+    }
+}
+
+impl Visitor<String> for InfixPrinter {
+    // Every left operand that needs parentheses starts where the chain starts, so all the
+    // opening ones go first and each closes before its operator
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, _expr: &Binary) -> String {
+        let (first, chain) = ast.chain(id);
+        let mut left = first;
+        let wrapped: Vec<bool> = chain
+            .iter()
+            .map(|(id, expr)| {
+                let wrap = ast[left].precedence() < Precedence::of_binary(&expr.op.token_type);
+                left = *id;
+                wrap
+            })
+            .collect();
+        let mut source = "(".repeat(wrapped.iter().filter(|wrap| **wrap).count());
+        source.push_str(&ast.accept(first, self));
+        for ((_, expr), wrap) in chain.iter().zip(wrapped) {
+            if wrap {
+                source.push(')');
+            }
+            let precedence = Precedence::of_binary(&expr.op.token_type);
+            source.push_str(&format!(" {} {}", expr.op.lexeme, self.operand(ast, expr.right, precedence.next())));
+        }
+        source
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> String {
+        match &expr.value {
+            LiteralValue::Number(n) => n.to_string(),
+            LiteralValue::String(s) => format!("\"{}\"", s),
+            value => value.to_string(),
+        }
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> String {
+        format!("({})", ast.accept(expr.expression, self))
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> String {
+        format!("{}{}", expr.op.lexeme, self.operand(ast, expr.right, Precedence::Unary))
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> String {
+        let arguments: Vec<String> = expr.arguments.iter().map(|argument| ast.accept(*argument, self)).collect();
+        format!("{}({})", self.operand(ast, expr.callee, Precedence::Primary), arguments.join(", "))
+    }
+
+    fn visit_get(&mut self, ast: &Ast, _id: ExprId, expr: &Get) -> String {
+        format!("{}.{}", self.operand(ast, expr.object, Precedence::Primary), expr.name.lexeme)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> String {
+        let elements: Vec<String> = expr.elements.iter().map(|element| ast.accept(*element, self)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> String {
+        format!("{}[{}]", self.operand(ast, expr.object, Precedence::Primary), ast.accept(expr.index, self))
+    }
+
+    fn visit_slice(&mut self, ast: &Ast, _id: ExprId, expr: &Slice) -> String {
+        let object = self.operand(ast, expr.object, Precedence::Primary);
+        let mut bound = |bound: Option<ExprId>| bound.map_or(String::new(), |id| ast.accept(id, self));
+        format!("{}[{}:{}]", object, bound(expr.start), bound(expr.stop))
+    }
+
+    fn visit_set_index(&mut self, ast: &Ast, _id: ExprId, expr: &SetIndex) -> String {
+        format!(
+            "{}[{}] = {}",
+            self.operand(ast, expr.object, Precedence::Primary),
+            ast.accept(expr.index, self),
+            ast.accept(expr.value, self)
+        )
+    }
+}
+
+// Dumps the tree as a single JSON object per node, children nested by field name
+pub struct JsonPrinter;
+
+impl JsonPrinter {
+    fn node(ast: &Ast, id: ExprId, kind: &str, fields: &[(&str, String)]) -> String {
+        let mut json = Self::open(kind, ast.span(id));
+        for (name, value) in fields {
+            json.push_str(&format!(",{}:{}", json_string(name), value));
+        }
+        json.push('}');
+        json
+    }
+
+    // A node up to its first field, left open for the fields to follow
+    fn open(kind: &str, (start, end): (usize, usize)) -> String {
+        format!("{{\"kind\":{},\"span\":{{\"start\":{},\"end\":{}}}", json_string(kind), start, end)
+    }
+}
+
+impl Visitor<String> for JsonPrinter {
+    // The spans of a chain grow from its innermost operator out, so they are worked out
+    // along the way instead of walking the chain again for each operator
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, _expr: &Binary) -> String {
+        let (first, chain) = ast.chain(id);
+        let mut start = ast.span(first).0;
+        let (mut heads, mut tails) = (Vec::new(), String::new());
+        for (_, expr) in &chain {
+            start = start.min(expr.op.line);
+            let end = ast.span(expr.right).1.max(expr.op.line);
+            let operator = json_string(&expr.op.lexeme);
+            heads.push(format!("{},\"operator\":{},\"left\":", Self::open("binary", (start, end)), operator));
+            tails.push_str(&format!(",\"right\":{}}}", ast.accept(expr.right, self)));
+        }
+        let mut json: String = heads.into_iter().rev().collect();
+        json.push_str(&ast.accept(first, self));
+        json.push_str(&tails);
+        json
+    }
+
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, expr: &Literal) -> String {
+        let value = match &expr.value {
+            LiteralValue::Number(n) if n.is_finite() => n.to_string(),
+            // JSON has no infinity or NaN, which an oversized literal or folding can produce
+            LiteralValue::Number(n) => json_string(&n.to_string()),
+            LiteralValue::String(s) => json_string(s),
+            LiteralValue::Bool(b) => b.to_string(),
+            LiteralValue::Nil => "null".to_string(),
+        };
+        Self::node(ast, id, "literal", &[("value", value)])
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) -> String {
+        Self::node(ast, id, "grouping", &[("expression", ast.accept(expr.expression, self))])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, expr: &Unary) -> String {
+        Self::node(ast, id, "unary", &[
+            ("operator", json_string(&expr.op.lexeme)),
+            ("right", ast.accept(expr.right, self)),
+        ])
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> String {
+        Self::node(ast, id, "variable", &[("name", json_string(&expr.name.lexeme))])
+    }
+
+    fn visit_call(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> String {
+        let arguments: Vec<String> = expr.arguments.iter().map(|argument| ast.accept(*argument, self)).collect();
+        Self::node(ast, id, "call", &[
+            ("callee", ast.accept(expr.callee, self)),
+            ("arguments", format!("[{}]", arguments.join(","))),
+        ])
+    }
+
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, expr: &Get) -> String {
+        Self::node(ast, id, "get", &[
+            ("object", ast.accept(expr.object, self)),
+            ("name", json_string(&expr.name.lexeme)),
+        ])
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, expr: &List) -> String {
+        let elements: Vec<String> = expr.elements.iter().map(|element| ast.accept(*element, self)).collect();
+        Self::node(ast, id, "list", &[("elements", format!("[{}]", elements.join(",")))])
+    }
+
+    fn visit_index(&mut self, ast: &Ast, id: ExprId, expr: &Index) -> String {
+        Self::node(ast, id, "index", &[
+            ("object", ast.accept(expr.object, self)),
+            ("index", ast.accept(expr.index, self)),
+        ])
+    }
+
+    fn visit_slice(&mut self, ast: &Ast, id: ExprId, expr: &Slice) -> String {
+        let object = ast.accept(expr.object, self);
+        let mut bound = |bound: Option<ExprId>| bound.map_or("null".to_string(), |id| ast.accept(id, self));
+        let (start, stop) = (bound(expr.start), bound(expr.stop));
+        Self::node(ast, id, "slice", &[("object", object), ("start", start), ("stop", stop)])
+    }
+
+    fn visit_set_index(&mut self, ast: &Ast, id: ExprId, expr: &SetIndex) -> String {
+        Self::node(ast, id, "set_index", &[
+            ("object", ast.accept(expr.object, self)),
+            ("index", ast.accept(expr.index, self)),
+            ("value", ast.accept(expr.value, self)),
+        ])
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
         }
     }
+    json.push('"');
+    json
+}
 
-    fn visit_grouping(&self, expr: &Grouping) -> String {
-        format!("(group {})", expr.expression.accept(self))
+// Emits a Graphviz digraph with one graph node per arena node, named after its id
+#[derive(Default)]
+pub struct DotPrinter {
+    lines: Vec<String>,
+}
+
+impl DotPrinter {
+    pub fn print(&mut self, ast: &Ast) -> String {
+        self.lines.clear();
+        ast.accept(ast.root, self);
+        format!("digraph ast {{\n{}}}", self.lines.concat())
     }
 
-    fn visit_unary(&self, expr: &Unary) -> String {
-        format!("({} {})", expr.op.lexeme, expr.right.accept(self))
+    fn node(&mut self, id: ExprId, label: &str, children: &[ExprId]) {
+        self.lines.push(format!("    n{} [label=\"{}\"];\n", id.0, label.replace('\\', "\\\\").replace('"', "\\\"")));
+        for child in children {
+            self.lines.push(format!("    n{} -> n{};\n", id.0, child.0));
+        }
+    }
+}
+
+impl Visitor<()> for DotPrinter {
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, _expr: &Binary) {
+        let (first, chain) = ast.chain(id);
+        for (id, expr) in chain.iter().rev() {
+            self.node(*id, &expr.op.lexeme, &[expr.left, expr.right]);
+        }
+        ast.accept(first, self);
+        for (_, expr) in &chain {
+            ast.accept(expr.right, self);
+        }
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, id: ExprId, expr: &Literal) {
+        match &expr.value {
+            LiteralValue::String(s) => self.node(id, &format!("\"{}\"", s), &[]),
+            value => self.node(id, &value.to_string(), &[]),
+        }
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) {
+        self.node(id, "group", &[expr.expression]);
+        ast.accept(expr.expression, self);
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, expr: &Unary) {
+        self.node(id, &expr.op.lexeme, &[expr.right]);
+        ast.accept(expr.right, self);
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, id: ExprId, expr: &Variable) {
+        self.node(id, &expr.name.lexeme, &[]);
+    }
+
+    fn visit_call(&mut self, ast: &Ast, id: ExprId, expr: &Call) {
+        let children: Vec<ExprId> = std::iter::once(expr.callee).chain(expr.arguments.iter().copied()).collect();
+        self.node(id, "call", &children);
+        for child in children {
+            ast.accept(child, self);
+        }
+    }
+
+    fn visit_get(&mut self, ast: &Ast, id: ExprId, expr: &Get) {
+        self.node(id, &format!(".{}", expr.name.lexeme), &[expr.object]);
+        ast.accept(expr.object, self);
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, expr: &List) {
+        self.node(id, "list", &expr.elements);
+        for element in &expr.elements {
+            ast.accept(*element, self);
+        }
+    }
+
+    fn visit_index(&mut self, ast: &Ast, id: ExprId, expr: &Index) {
+        self.node(id, "[]", &[expr.object, expr.index]);
+        ast.accept(expr.object, self);
+        ast.accept(expr.index, self);
+    }
+
+    fn visit_slice(&mut self, ast: &Ast, id: ExprId, expr: &Slice) {
+        let children: Vec<ExprId> = std::iter::once(expr.object).chain(expr.start).chain(expr.stop).collect();
+        self.node(id, "[:]", &children);
+        for child in children {
+            ast.accept(child, self);
+        }
+    }
+
+    fn visit_set_index(&mut self, ast: &Ast, id: ExprId, expr: &SetIndex) {
+        self.node(id, "[]=", &[expr.object, expr.index, expr.value]);
+        ast.accept(expr.object, self);
+        ast.accept(expr.index, self);
+        ast.accept(expr.value, self);
     }
 }
 
@@ -82,80 +825,402 @@ impl Visitor<String> for AstPrinter {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lox_parser::LoxParser;
+    use crate::lox_tokenizer::LoxTokenizer;
     use crate::token_types::TokenType;
 
+    fn number(ast: &mut Ast, n: f64) -> ExprId {
+        ast.add(ExprEnum::Literal(Literal {
+            value: LiteralValue::Number(n),
+            line: 1,
+        }))
+    }
+
     #[test]
     fn test_ast_printer() {
-        let expr = ExprEnum::Binary(Binary {
-            left: Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(1),
-            })),
+        let mut ast = Ast::default();
+        let left = number(&mut ast, 1.0);
+        let right = number(&mut ast, 2.0);
+        let expr = ast.add(ExprEnum::Binary(Binary {
+            left,
             op: Token::new(TokenType::Plus, "+".to_string(), None, 1),
-            right: Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(2),
-            })),
-        });
+            right,
+        }));
 
-        let ast_printer = AstPrinter {};
-        let result = expr.accept(&ast_printer);
-        assert_eq!(result, "(+ 1 2)");
+        let mut ast_printer = AstPrinter {};
+        let result = ast.accept(expr, &mut ast_printer);
+        assert_eq!(result, "(+ 1.0 2.0)");
     }
 
     #[test]
     fn test_ast_printer_grouping() {
-        let expr = ExprEnum::Grouping(Grouping {
-            expression: Box::new(ExprEnum::Binary(Binary {
-                left: Box::new(ExprEnum::Literal(Literal {
-                    value: Box::new(1),
-                })),
-                op: Token::new(TokenType::Plus, "+".to_string(), None, 1),
-                right: Box::new(ExprEnum::Literal(Literal {
-                    value: Box::new(2),
-                })),
-            })),
-        });
+        let mut ast = Ast::default();
+        let left = number(&mut ast, 1.0);
+        let right = number(&mut ast, 2.0);
+        let expression = ast.add(ExprEnum::Binary(Binary {
+            left,
+            op: Token::new(TokenType::Plus, "+".to_string(), None, 1),
+            right,
+        }));
+        let expr = ast.add(ExprEnum::Grouping(Grouping { expression }));
 
-        let ast_printer = AstPrinter {};
-        let result = expr.accept(&ast_printer);
-        assert_eq!(result, "(group (+ 1 2))");
+        let mut ast_printer = AstPrinter {};
+        let result = ast.accept(expr, &mut ast_printer);
+        assert_eq!(result, "(group (+ 1.0 2.0))");
     }
 
     #[test]
     fn test_ast_printer_unary() {
-        let expr = ExprEnum::Unary(Unary {
+        let mut ast = Ast::default();
+        let right = number(&mut ast, 1.0);
+        let expr = ast.add(ExprEnum::Unary(Unary {
             op: Token::new(TokenType::Minus, "-".to_string(), None, 1),
-            right: Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(1),
-            })),
-        });
+            right,
+        }));
 
-        let ast_printer = AstPrinter {};
-        let result = expr.accept(&ast_printer);
-        assert_eq!(result, "(- 1)");
+        let mut ast_printer = AstPrinter {};
+        let result = ast.accept(expr, &mut ast_printer);
+        assert_eq!(result, "(- 1.0)");
     }
 
     #[test]
     fn test_ast_printer_unary_string() {
-        let expr = ExprEnum::Unary(Unary {
+        let mut ast = Ast::default();
+        let right = number(&mut ast, 44.0);
+        let expr = ast.add(ExprEnum::Unary(Unary {
             op: Token::new(TokenType::Minus, "-".to_string(), None, 1),
-            right: Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(44),
-            })),
-        });
+            right,
+        }));
 
-        let ast_printer = AstPrinter {};
-        let result = expr.accept(&ast_printer);
-        assert_eq!(result, "(- 44)");
+        let mut ast_printer = AstPrinter {};
+        let result = ast.accept(expr, &mut ast_printer);
+        assert_eq!(result, "(- 44.0)");
     }
 
     #[test]
     fn test_literal() {
-        let expr = ExprEnum::Literal(Literal {
-            value: Box::new("44.0"),
-        });
+        let mut ast = Ast::default();
+        let expr = number(&mut ast, 44.0);
 
-        let ast_printer = AstPrinter {};
-        let result = expr.accept(&ast_printer);
+        let mut ast_printer = AstPrinter {};
+        let result = ast.accept(expr, &mut ast_printer);
         assert_eq!(result, "44.0");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_node_ids_are_stable() {
+        let ast = parse("1 + 2 * 3");
+        assert_eq!(ast.root, ExprId(4));
+        match &ast[ast.root] {
+            ExprEnum::Binary(binary) => {
+                assert_eq!(binary.left, ExprId(0));
+                assert_eq!(binary.right, ExprId(3));
+                assert_eq!(ast.accept(binary.right, &mut AstPrinter {}), "(* 2.0 3.0)");
+            }
+            other => panic!("Expected binary, got {:?}", other),
+        }
+    }
+
+    fn parse(source: &str) -> Ast {
+        let mut tokenizer = LoxTokenizer::default();
+        let tokens = tokenizer.tokenize(source);
+        assert!(!tokenizer.had_error, "failed to tokenize {}", source);
+        let mut parser = LoxParser::new(tokens);
+        let ast = parser.parse();
+        assert!(!parser.has_error, "failed to parse {}", source);
+        ast
+    }
+
+    fn print(ast: &Ast) -> String {
+        ast.accept(ast.root, &mut InfixPrinter {})
+    }
+
+    #[test]
+    fn test_infix_printer() {
+        let ast = parse("1 + 2 * 3 == !true");
+        assert_eq!(print(&ast), "1 + 2 * 3 == !true");
+    }
+
+    #[test]
+    fn test_infix_printer_keeps_source_groupings() {
+        let ast = parse("(1 + 2) * -(a - \"b\") / (nil)");
+        assert_eq!(print(&ast), "(1 + 2) * -(a - \"b\") / (nil)");
+    }
+
+    #[test]
+    fn test_infix_printer_calls_and_properties() {
+        let ast = parse("-f(1, a + 2)(g()) * (h)(!x)");
+        assert_eq!(print(&ast), "-f(1, a + 2)(g()) * (h)(!x)");
+        assert_eq!(ast.accept(ast.root, &mut AstPrinter {}), "(* (- (call (call f 1.0 (+ a 2.0)) (call g))) (call (group h) (! x)))");
+
+        let ast = parse("-a.b(c.d).e * (1 + f).g");
+        assert_eq!(print(&ast), "-a.b(c.d).e * (1 + f).g");
+        assert_eq!(ast.accept(ast.root, &mut AstPrinter {}), "(* (- (. (call (. a b) (. c d)) e)) (. (group (+ 1.0 f)) g))");
+    }
+
+    #[test]
+    fn test_infix_printer_adds_required_parentheses() {
+        let minus = Token::new(TokenType::Minus, "-".to_string(), None, 1);
+        let star = Token::new(TokenType::Star, "*".to_string(), None, 1);
+        let difference = |ast: &mut Ast, left, right| {
+            ast.add(ExprEnum::Binary(Binary { left, op: minus.clone(), right }))
+        };
+
+        // 1 - (2 - 3) must not be printed as the left-associative 1 - 2 - 3
+        let mut ast = Ast::default();
+        let (one, two, three) = (number(&mut ast, 1.0), number(&mut ast, 2.0), number(&mut ast, 3.0));
+        let right = difference(&mut ast, two, three);
+        let expr = difference(&mut ast, one, right);
+        assert_eq!(ast.accept(expr, &mut InfixPrinter {}), "1 - (2 - 3)");
+
+        let left = difference(&mut ast, one, two);
+        let expr = difference(&mut ast, left, three);
+        assert_eq!(ast.accept(expr, &mut InfixPrinter {}), "1 - 2 - 3");
+
+        let factor = number(&mut ast, 2.5);
+        let expr = ast.add(ExprEnum::Binary(Binary { left, op: star, right: factor }));
+        assert_eq!(ast.accept(expr, &mut InfixPrinter {}), "(1 - 2) * 2.5");
+
+        let expr = ast.add(ExprEnum::Unary(Unary { op: minus.clone(), right: left }));
+        assert_eq!(ast.accept(expr, &mut InfixPrinter {}), "-(1 - 2)");
+    }
+
+    #[test]
+    fn test_json_printer() {
+        let ast = parse("-x ==\n(\"a\\b\" + nil)");
+        assert_eq!(
+            ast.accept(ast.root, &mut JsonPrinter {}),
+            concat!(
+                r#"{"kind":"binary","span":{"start":1,"end":2},"operator":"==","#,
+                r#""left":{"kind":"unary","span":{"start":1,"end":1},"operator":"-","#,
+                r#""right":{"kind":"variable","span":{"start":1,"end":1},"name":"x"}},"#,
+                r#""right":{"kind":"grouping","span":{"start":2,"end":2},"#,
+                r#""expression":{"kind":"binary","span":{"start":2,"end":2},"operator":"+","#,
+                r#""left":{"kind":"literal","span":{"start":2,"end":2},"value":"a\\b"},"#,
+                r#""right":{"kind":"literal","span":{"start":2,"end":2},"value":null}}}}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_printer_non_finite_numbers() {
+        let ast = parse(&format!("1{}", "0".repeat(400)));
+        assert_eq!(
+            ast.accept(ast.root, &mut JsonPrinter {}),
+            r#"{"kind":"literal","span":{"start":1,"end":1},"value":"inf"}"#
+        );
+    }
+
+    #[test]
+    fn test_json_printer_call() {
+        let ast = parse("f(1,\nx)");
+        assert_eq!(
+            ast.accept(ast.root, &mut JsonPrinter {}),
+            concat!(
+                r#"{"kind":"call","span":{"start":1,"end":2},"#,
+                r#""callee":{"kind":"variable","span":{"start":1,"end":1},"name":"f"},"#,
+                r#""arguments":[{"kind":"literal","span":{"start":1,"end":1},"value":1},"#,
+                r#"{"kind":"variable","span":{"start":2,"end":2},"name":"x"}]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_printers_walk_long_chains() {
+        let source = vec!["1 - 2 * x"; 5000].join(" + ");
+        assert_eq!(print(&crate::parse(&source).unwrap()), source);
+
+        let ast = crate::parse(&vec!["x"; 20_000].join(" +\n")).unwrap();
+        let sexpr = ast.accept(ast.root, &mut AstPrinter {});
+        assert!(sexpr.starts_with("(+ (+ (+ "));
+        assert!(sexpr.ends_with(" x) x) x)"));
+        let json = ast.accept(ast.root, &mut JsonPrinter {});
+        assert!(json.starts_with(concat!(
+            r#"{"kind":"binary","span":{"start":1,"end":20000},"operator":"+","#,
+            r#""left":{"kind":"binary","span":{"start":1,"end":19999},"operator":"+","left":"#,
+        )));
+        assert!(json.ends_with(r#","right":{"kind":"variable","span":{"start":20000,"end":20000},"name":"x"}}"#));
+        assert_eq!(DotPrinter::default().print(&ast).matches("[label=").count(), 39_999);
+    }
+
+    #[test]
+    fn test_dot_printer() {
+        let ast = parse("(1 + true)");
+        assert_eq!(
+            DotPrinter::default().print(&ast),
+            concat!(
+                "digraph ast {\n",
+                "    n3 [label=\"group\"];\n",
+                "    n3 -> n2;\n",
+                "    n2 [label=\"+\"];\n",
+                "    n2 -> n0;\n",
+                "    n2 -> n1;\n",
+                "    n0 [label=\"1.0\"];\n",
+                "    n1 [label=\"true\"];\n",
+                "}",
+            )
+        );
+    }
+
+    #[derive(Default)]
+    struct LiteralCollector {
+        literals: Vec<String>,
+    }
+
+    impl Walker for LiteralCollector {
+        fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) {
+            self.literals.push(expr.value.to_string());
+        }
+    }
+
+    #[test]
+    fn test_walker_keeps_state() {
+        let ast = parse("1 + (a * \"b\") - -f(nil, 2)[true:]");
+        let mut counter = LiteralCollector::default();
+        ast.walk(ast.root, &mut counter);
+        assert_eq!(counter.literals, ["1.0", "b", "nil", "2.0", "true"]);
+
+        let ast = crate::parse(&vec!["1"; 20_000].join(" + ")).unwrap();
+        let mut counter = LiteralCollector::default();
+        ast.walk(ast.root, &mut counter);
+        assert_eq!(counter.literals.len(), 20_000);
+    }
+
+    struct Identity;
+
+    impl Folder for Identity {}
+
+    #[test]
+    fn test_folder_defaults_rebuild_the_tree() {
+        let ast = parse("(1 + a) * -(2 / \"b\") == f(nil).g()");
+        assert_eq!(ast.clone().fold(&mut Identity), ast);
+    }
+
+    struct Ungroup;
+
+    impl Folder for Ungroup {
+        fn fold_grouping(&mut self, _ast: &mut Ast, expr: Grouping) -> ExprId {
+            expr.expression
+        }
+    }
+
+    #[derive(Default)]
+    struct IndexCounter {
+        indexes: usize,
+    }
+
+    impl Folder for IndexCounter {
+        fn fold_index(&mut self, ast: &mut Ast, expr: Index) -> ExprId {
+            self.indexes += 1;
+            ast.add(ExprEnum::Index(expr))
+        }
+    }
+
+    #[test]
+    fn test_fold_skips_unreachable_nodes() {
+        // The parser turns the `a[0]` target into a SetIndex and leaves the Index node behind
+        let ast = parse("a[0] = b[1]");
+        let mut counter = IndexCounter::default();
+        let folded = ast.fold(&mut counter);
+        assert_eq!(counter.indexes, 1);
+        assert_eq!(folded.accept(folded.root, &mut AstPrinter {}), "([]= a 0.0 ([] b 1.0))");
+    }
+
+    #[test]
+    fn test_folder_rewrites_nodes() {
+        let ast = parse("((1 + a)) * (2)").fold(&mut Ungroup);
+        assert_eq!(ast.accept(ast.root, &mut AstPrinter {}), "(* (+ 1.0 a) 2.0)");
+        assert_eq!(ast.accept(ast.root, &mut InfixPrinter {}), "(1 + a) * 2");
+    }
+
+    // Minimal xorshift generator so the property test is reproducible without extra crates
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    fn random_expr(ast: &mut Ast, rng: &mut Rng, depth: u32) -> ExprId {
+        let binary = [
+            (TokenType::BangEqual, "!="), (TokenType::EqualEqual, "=="),
+            (TokenType::Greater, ">"), (TokenType::GreaterEqual, ">="),
+            (TokenType::Less, "<"), (TokenType::LessEqual, "<="),
+            (TokenType::Minus, "-"), (TokenType::Plus, "+"),
+            (TokenType::Slash, "/"), (TokenType::Star, "*"),
+        ];
+        let choice = if depth == 0 { rng.below(5) } else { rng.below(9) };
+        let expr = match choice {
+            0 => ExprEnum::Literal(Literal { value: LiteralValue::Number(rng.below(1000) as f64 / 4.0), line: 1 }),
+            1 => ExprEnum::Literal(Literal { value: LiteralValue::String(format!("s{}", rng.below(10))), line: 1 }),
+            2 => ExprEnum::Literal(Literal { value: LiteralValue::Bool(rng.below(2) == 0), line: 1 }),
+            3 => ExprEnum::Literal(Literal { value: LiteralValue::Nil, line: 1 }),
+            4 => ExprEnum::Variable(Variable {
+                name: Token::new(TokenType::Identifier, format!("v{}", rng.below(10)), None, 1),
+            }),
+            5 => {
+                let (op, lexeme) = if rng.below(2) == 0 { (TokenType::Bang, "!") } else { (TokenType::Minus, "-") };
+                ExprEnum::Unary(Unary {
+                    op: Token::new(op, lexeme.to_string(), None, 1),
+                    right: random_expr(ast, rng, depth - 1),
+                })
+            }
+            6 => ExprEnum::Grouping(Grouping { expression: random_expr(ast, rng, depth - 1) }),
+            _ => {
+                let (op, lexeme) = binary[rng.below(binary.len() as u64) as usize].clone();
+                let left = random_expr(ast, rng, depth - 1);
+                let right = random_expr(ast, rng, depth - 1);
+                ExprEnum::Binary(Binary {
+                    left,
+                    op: Token::new(op, lexeme.to_string(), None, 1),
+                    right,
+                })
+            }
+        };
+        ast.add(expr)
+    }
+
+    // Copies the subtree at `id` into `out`, skipping grouping nodes
+    fn without_groupings(ast: &Ast, id: ExprId, out: &mut Ast) -> ExprId {
+        let expr = match &ast[id] {
+            ExprEnum::Grouping(grouping) => return without_groupings(ast, grouping.expression, out),
+            ExprEnum::Binary(binary) => ExprEnum::Binary(Binary {
+                left: without_groupings(ast, binary.left, out),
+                op: binary.op.clone(),
+                right: without_groupings(ast, binary.right, out),
+            }),
+            ExprEnum::Unary(unary) => ExprEnum::Unary(Unary {
+                op: unary.op.clone(),
+                right: without_groupings(ast, unary.right, out),
+            }),
+            other => other.clone(),
+        };
+        out.add(expr)
+    }
+
+    fn shape(ast: &Ast, id: ExprId) -> Ast {
+        let mut out = Ast::default();
+        out.root = without_groupings(ast, id, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_infix_printer_round_trip() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..500 {
+            let mut generated = Ast::default();
+            let expr = random_expr(&mut generated, &mut rng, 5);
+            let source = generated.accept(expr, &mut InfixPrinter {});
+            let parsed = parse(&source);
+            assert_eq!(shape(&parsed, parsed.root), shape(&generated, expr), "{}", source);
+
+            let reprinted = print(&parsed);
+            assert_eq!(reprinted, source);
+            assert_eq!(parse(&reprinted), parsed, "{}", source);
+        }
+    }
+}
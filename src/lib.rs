@@ -0,0 +1,52 @@
+//! Lox interpreter from Crafting Interpreters, usable as a library.
+//!
+//! `Interpreter::eval` runs source code; `tokenize` and `parse` expose the
+//! front end for tooling, and the `expr` visitors print, walk or rewrite a parsed `Ast`.
+
+pub mod expr;
+pub mod token;
+pub mod token_types;
+mod lox_error;
+mod lox_tokenizer;
+mod lox_parser;
+mod lox_optimizer;
+mod lox_interpreter;
+mod lox_list;
+mod lox_capabilities;
+mod lox_stdlib;
+
+pub use lox_capabilities::{Capabilities, Capability};
+pub use lox_error::{Diagnostic, LoxError};
+pub use lox_interpreter::{HostObject, Interpreter, Limits, NativeFunction, Value};
+pub use lox_list::LoxList;
+pub use lox_optimizer::LoxOptimizer;
+pub use lox_tokenizer::LoxTokenizer;
+
+use crate::expr::Ast;
+use crate::lox_parser::LoxParser;
+use crate::token::Token;
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, LoxError> {
+    let mut tokenizer = LoxTokenizer::default();
+    let tokens = tokenizer.tokenize(source);
+    if tokenizer.had_error {
+        return Err(LoxError::Syntax(tokenizer.errors));
+    }
+    Ok(tokens)
+}
+
+// Parentheses, unary operators and postfix chains are bounded by the default depth limit,
+// as in Interpreter::eval. Flat chains like `1 + 2 + 3` are not; the printers walk them in a loop
+pub fn parse(source: &str) -> Result<Ast, LoxError> {
+    parse_with_max_depth(source, Limits::default().max_depth)
+}
+
+pub(crate) fn parse_with_max_depth(source: &str, max_depth: Option<usize>) -> Result<Ast, LoxError> {
+    let mut parser = LoxParser::new(tokenize(source)?);
+    parser.max_depth = max_depth;
+    let ast = parser.parse();
+    if parser.has_error {
+        return Err(LoxError::Syntax(parser.errors));
+    }
+    Ok(ast)
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::lox_interpreter::Value;
+
+// Access to the outside world that a native function can require
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Capability {
+    // Reading files; the first argument of the call is the path
+    Read,
+    Write,
+    Env,
+    Time,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capability::Read => write!(f, "read"),
+            Capability::Write => write!(f, "write"),
+            Capability::Env => write!(f, "env"),
+            Capability::Time => write!(f, "time"),
+        }
+    }
+}
+
+// What the host grants to scripts. The default grants nothing
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Capabilities {
+    // Directories whose files may be read, including subdirectories
+    pub read: Vec<PathBuf>,
+    pub write: bool,
+    pub env: bool,
+    pub time: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Capabilities {
+            read: vec![PathBuf::from("/")],
+            write: true,
+            env: true,
+            time: true,
+        }
+    }
+
+    pub(crate) fn check(&self, function: &str, capability: Capability, args: &[Value]) -> Result<(), String> {
+        let granted = match capability {
+            Capability::Read => match args.first() {
+                Some(Value::String(path)) => self.can_read(Path::new(path)),
+                _ => !self.read.is_empty(),
+            },
+            Capability::Write => self.write,
+            Capability::Env => self.env,
+            Capability::Time => self.time,
+        };
+        if granted {
+            return Ok(());
+        }
+        match (capability, args.first()) {
+            (Capability::Read, Some(Value::String(path))) => {
+                Err(format!("{}() needs the 'read' capability for {}.", function, path))
+            }
+            _ => Err(format!("{}() needs the '{}' capability.", function, capability)),
+        }
+    }
+
+    // Both sides are canonicalized so `..` and symlinks cannot leave a granted directory.
+    // A missing file is judged by its nearest existing directory, so the script gets the real error on access
+    fn can_read(&self, path: &Path) -> bool {
+        let Some(path) = resolve(path) else {
+            return false;
+        };
+        self.read
+            .iter()
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .any(|dir| path.starts_with(dir))
+    }
+}
+
+// Canonicalizes the nearest existing ancestor of `path` and re-joins the missing components below it.
+// An empty parent is the current directory; a `..` below a missing directory cannot be resolved
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        let base = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+        if let Ok(canonical) = fs::canonicalize(base) {
+            return Some(missing.iter().rev().fold(canonical, |path, name| path.join(name)));
+        }
+        missing.push(existing.file_name()?);
+        existing = existing.parent()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(path: &Path) -> Vec<Value> {
+        vec![Value::String(path.display().to_string())]
+    }
+
+    #[test]
+    fn test_nothing_is_granted_by_default() {
+        let capabilities = Capabilities::default();
+        assert_eq!(capabilities.check("clock", Capability::Time, &[]), Err("clock() needs the 'time' capability.".to_string()));
+        assert!(capabilities.check("getenv", Capability::Env, &[]).is_err());
+        assert!(capabilities.check("write", Capability::Write, &[]).is_err());
+        assert!(Capabilities::all().check("clock", Capability::Time, &[]).is_ok());
+    }
+
+    #[test]
+    fn test_read_is_limited_to_granted_directories() {
+        let root = std::env::temp_dir().join(format!("lox_capabilities_{}", std::process::id()));
+        let granted = root.join("granted");
+        fs::create_dir_all(&granted).unwrap();
+        fs::write(granted.join("a.txt"), "a").unwrap();
+        fs::write(root.join("secret.txt"), "s").unwrap();
+        let capabilities = Capabilities {
+            read: vec![granted.clone()],
+            ..Capabilities::default()
+        };
+
+        assert!(capabilities.check("read", Capability::Read, &path(&granted.join("a.txt"))).is_ok());
+        let escape = granted.join("..").join("secret.txt");
+        assert_eq!(
+            capabilities.check("read", Capability::Read, &path(&escape)),
+            Err(format!("read() needs the 'read' capability for {}.", escape.display()))
+        );
+        assert!(capabilities.check("read", Capability::Read, &path(&root.join("secret.txt"))).is_err());
+        assert!(capabilities.check("read", Capability::Read, &path(&granted.join("missing.txt"))).is_ok());
+        let nested = granted.join("missing").join("nested.txt");
+        assert!(capabilities.check("read", Capability::Read, &path(&nested)).is_ok());
+        let escape = granted.join("missing").join("..").join("..").join("secret.txt");
+        assert!(capabilities.check("read", Capability::Read, &path(&escape)).is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_relative_paths_are_judged_from_the_current_directory() {
+        let capabilities = Capabilities {
+            read: vec![PathBuf::from(".")],
+            ..Capabilities::default()
+        };
+        assert!(capabilities.check("read", Capability::Read, &path(Path::new("Cargo.toml"))).is_ok());
+        assert!(capabilities.check("read", Capability::Read, &path(Path::new("missing.txt"))).is_ok());
+        assert!(capabilities.check("read", Capability::Read, &path(Path::new("missing/nested.txt"))).is_ok());
+        assert!(capabilities.check("read", Capability::Read, &path(Path::new("../missing.txt"))).is_err());
+    }
+}
@@ -0,0 +1,458 @@
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::str::FromStr;
+use std::time::Duration;
+use std::path::PathBuf;
+use interpreter_starter_rust::{Capabilities, Limits, LoxError};
+
+// Exit codes follow the BSD sysexits convention used by jlox
+pub(crate) const EXIT_USAGE: i32 = 64;
+pub(crate) const EXIT_DATA: i32 = 65;
+pub(crate) const EXIT_INPUT: i32 = 66;
+pub(crate) const EXIT_RUNTIME: i32 = 70;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum DiagnosticFormat {
+    Human,
+    Json,
+}
+
+// Prints errors from the library to stderr the way --color and --diagnostics ask for
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub(crate) struct Reporter {
+    color: bool,
+    json: bool,
+}
+
+impl Reporter {
+    pub(crate) fn new(color: ColorChoice, format: DiagnosticFormat) -> Self {
+        let color = match color {
+            ColorChoice::Auto => io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        };
+        Reporter {
+            color,
+            json: format == DiagnosticFormat::Json,
+        }
+    }
+
+    pub(crate) fn report(&self, err: &LoxError) {
+        match err {
+            _ if self.json => eprintln!("{}", err.to_json()),
+            LoxError::Syntax(diagnostics) if self.color => {
+                for diagnostic in diagnostics {
+                    eprintln!(
+                        "[line {}] \x1b[1;31mError\x1b[0m{}: {}",
+                        diagnostic.line, diagnostic.location, diagnostic.message
+                    );
+                }
+            }
+            _ => eprintln!("{}", err),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    Tokenize,
+    Parse,
+    Evaluate,
+    Repl,
+    Help(Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Source {
+    File(String),
+    Stdin,
+    Inline(String),
+}
+
+impl Source {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => "<stdin>",
+            Source::Inline(_) => "<-e>",
+        }
+    }
+
+    pub(crate) fn read(&self) -> io::Result<String> {
+        match self {
+            Source::File(path) => fs::read_to_string(path),
+            Source::Stdin => {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                Ok(source)
+            }
+            Source::Inline(code) => Ok(code.clone()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) struct LoxCli {
+    pub(crate) command: Command,
+    pub(crate) sources: Vec<Source>,
+    pub(crate) verbose: bool,
+    pub(crate) color: ColorChoice,
+    pub(crate) diagnostics: DiagnosticFormat,
+    pub(crate) format: String,
+    pub(crate) optimized: bool,
+    pub(crate) optimize: bool,
+    pub(crate) limits: Limits,
+    pub(crate) capabilities: Capabilities,
+    pub(crate) deterministic: bool,
+}
+
+impl LoxCli {
+    // Parses the arguments after the program name; global options may appear anywhere
+    pub(crate) fn parse(args: &[String]) -> Result<LoxCli, String> {
+        let mut cli = LoxCli {
+            command: Command::Repl,
+            sources: Vec::new(),
+            verbose: false,
+            color: ColorChoice::Auto,
+            diagnostics: DiagnosticFormat::Human,
+            format: "sexpr".to_string(),
+            optimized: false,
+            optimize: false,
+            limits: Limits::default(),
+            capabilities: Capabilities::default(),
+            deterministic: false,
+        };
+        let mut command = None;
+        let mut help = false;
+        let mut parse_options = Vec::new();
+        let mut run_options = Vec::new();
+
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+                _ => (arg.as_str(), None),
+            };
+            match (name, value) {
+                ("-h" | "--help", None) => help = true,
+                ("--verbose", None) => cli.verbose = true,
+                ("--color", Some(value)) => {
+                    cli.color = match value {
+                        "auto" => ColorChoice::Auto,
+                        "always" => ColorChoice::Always,
+                        "never" => ColorChoice::Never,
+                        _ => return Err(format!("Invalid --color value: {} (expected auto, always or never)", value)),
+                    }
+                }
+                ("--diagnostics", Some(value)) => {
+                    cli.diagnostics = match value {
+                        "human" => DiagnosticFormat::Human,
+                        "json" => DiagnosticFormat::Json,
+                        _ => return Err(format!("Invalid --diagnostics value: {} (expected human or json)", value)),
+                    }
+                }
+                ("--format", Some(value)) => {
+                    if !["sexpr", "json", "dot"].contains(&value) {
+                        return Err(format!("Unknown format: {} (expected sexpr, json or dot)", value));
+                    }
+                    cli.format = value.to_string();
+                    parse_options.push(name);
+                }
+                ("--optimized", None) => {
+                    cli.optimized = true;
+                    parse_options.push(name);
+                }
+                ("--optimize", None) => {
+                    cli.optimize = true;
+                    run_options.push(name);
+                }
+                ("--max-steps", Some(value)) => {
+                    cli.limits.max_steps = Some(number(name, value)?);
+                    run_options.push(name);
+                }
+                ("--max-memory", Some(value)) => {
+                    cli.limits.max_memory = Some(number(name, value)?);
+                    run_options.push(name);
+                }
+                ("--max-depth", Some(value)) => {
+                    cli.limits.max_depth = Some(number(name, value)?);
+                    run_options.push(name);
+                }
+                ("--timeout", Some(value)) => {
+                    cli.limits.timeout = Some(Duration::from_millis(number(name, value)?));
+                    run_options.push(name);
+                }
+                ("--allow-read", Some(dir)) => {
+                    cli.capabilities.read.push(PathBuf::from(dir));
+                    run_options.push(name);
+                }
+                ("--allow-write", None) => {
+                    cli.capabilities.write = true;
+                    run_options.push(name);
+                }
+                ("--allow-env", None) => {
+                    cli.capabilities.env = true;
+                    run_options.push(name);
+                }
+                ("--allow-time", None) => {
+                    cli.capabilities.time = true;
+                    run_options.push(name);
+                }
+                ("--deterministic", None) => {
+                    cli.deterministic = true;
+                    run_options.push(name);
+                }
+                ("-e", None) => match rest.next() {
+                    Some(code) => cli.sources.push(Source::Inline(code.clone())),
+                    None => return Err("Missing code after -e".to_string()),
+                },
+                ("-", None) => cli.sources.push(Source::Stdin),
+                _ if name.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ if command.is_none() => command = Some(arg.clone()),
+                _ => cli.sources.push(Source::File(arg.clone())),
+            }
+        }
+
+        let topic = cli.sources.iter().find_map(|source| match source {
+            Source::File(name) => Some(name.clone()),
+            _ => None,
+        });
+
+        cli.command = match command.as_deref() {
+            None => Command::Repl,
+            Some("tokenize") => Command::Tokenize,
+            Some("parse") => Command::Parse,
+            Some("evaluate") => Command::Evaluate,
+            Some("repl") => Command::Repl,
+            Some("help") => Command::Help(topic.clone()),
+            Some(other) => return Err(format!("Unknown command: {}", other)),
+        };
+        if help {
+            cli.command = Command::Help(command.filter(|command| command != "help").or(topic));
+        }
+
+        match cli.command {
+            Command::Tokenize | Command::Parse | Command::Evaluate if cli.sources.is_empty() => {
+                return Err("Missing source: give a file, - for stdin, or -e <code>".to_string());
+            }
+            Command::Repl if !cli.sources.is_empty() => {
+                return Err("repl does not take a source".to_string());
+            }
+            _ => {}
+        }
+        if cli.command != Command::Parse {
+            if let Some(option) = parse_options.first() {
+                return Err(format!("{} is only supported by parse", option));
+            }
+        }
+        if !matches!(cli.command, Command::Evaluate | Command::Repl | Command::Help(_)) {
+            if let Some(option) = run_options.first() {
+                return Err(format!("{} is only supported by evaluate and repl", option));
+            }
+        }
+        Ok(cli)
+    }
+}
+
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {} value: {} (expected a whole number)", option, value))
+}
+
+pub(crate) fn help(program: &str, command: Option<&str>) -> Result<String, String> {
+    let global = "\
+Sources:
+  <file>                 Read a file; several files are processed in order
+  -                      Read standard input
+  -e <code>              Use <code> as the source
+
+Options:
+  --verbose              Echo each source and its tokens to stderr
+  --color=<when>         Color diagnostics: auto, always or never (default auto)
+  --diagnostics=<style>  Print diagnostics as human text or json (default human)
+  -h, --help             Show help";
+
+    let run = "\
+Optimization:
+  --optimize             Fold constant subexpressions before evaluating
+
+Capabilities, none of which is granted by default:
+  --allow-read=<dir>     Let scripts read files under <dir>; may be repeated
+  --allow-write          Let scripts write files
+  --allow-env            Let scripts read environment variables
+  --allow-time           Let scripts read the clock (not needed with --deterministic)
+
+Reproducibility:
+  --deterministic        Use a virtual clock that advances one microsecond per
+                         evaluated expression, so every run prints the same output
+
+Limits, checked for each evaluated source or entry (exit code 70 when exceeded):
+  --max-steps=<n>        Evaluate at most <n> expression nodes
+  --max-memory=<bytes>   Create at most <bytes> of string data and list slots
+  --max-depth=<n>        Nest subexpressions at most <n> deep (default 128); a flat
+                         chain like 1 + 2 + 3 is one level however long
+  --timeout=<ms>         Stop after <ms> milliseconds";
+
+    match command {
+        None => Ok(format!(
+            "Usage: {} [options] <command> [command options] <source>...
+
+Commands:
+  tokenize               Print the tokens of each source
+  parse                  Print the syntax tree of each source
+  evaluate               Print the value of each source expression
+  repl                   Start an interactive session (default without arguments)
+  help <command>         Show help for a command
+
+{}
+
+Exit codes: 64 usage, 65 invalid source, 66 unreadable input, 70 runtime error",
+            program, global
+        )),
+        Some("tokenize") => Ok(format!(
+            "Usage: {} tokenize [options] <source>...
+
+Print one token per line, ending with EOF.
+
+{}",
+            program, global
+        )),
+        Some("parse") => Ok(format!(
+            "Usage: {} parse [options] [--format=<format>] [--optimized] <source>...
+
+Print the syntax tree of each source expression.
+
+Parse options:
+  --format=<format>      sexpr, json or dot (default sexpr)
+  --optimized            Fold constant subexpressions before printing
+
+{}",
+            program, global
+        )),
+        Some("evaluate") => Ok(format!(
+            "Usage: {} evaluate [options] [capabilities] [limits] <source>...
+
+Evaluate each source expression and print its value.
+
+{}
+
+{}",
+            program, run, global
+        )),
+        Some("repl") => Ok(format!(
+            "Usage: {} [repl] [options] [capabilities] [limits]
+
+Evaluate expressions line by line; unbalanced parentheses, braces or brackets continue on the next line.
+
+REPL commands:
+  :tokens <code>         Print the tokens of <code>
+  :ast <code>            Print the syntax tree of <code>
+  :env                   List the global variables and their values
+  :type <expr>           Evaluate <expr> and print its type
+  :load <file>           Evaluate a file
+  :reset                 Drop pending input
+  :time <expr>           Evaluate <expr> and print how long it took
+
+{}
+
+{}",
+            program, run, global
+        )),
+        Some(other) => Err(format!("Unknown command: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<LoxCli, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        LoxCli::parse(&args)
+    }
+
+    #[test]
+    fn test_no_arguments_start_repl() {
+        assert_eq!(parse(&[]).unwrap().command, Command::Repl);
+    }
+
+    #[test]
+    fn test_global_options_anywhere() {
+        let cli = parse(&["--verbose", "parse", "a.lox", "--color=never", "-", "-e", "1", "b.lox"]).unwrap();
+        assert_eq!(cli.command, Command::Parse);
+        assert!(cli.verbose);
+        assert_eq!(cli.color, ColorChoice::Never);
+        assert_eq!(
+            cli.sources,
+            vec![
+                Source::File("a.lox".to_string()),
+                Source::Stdin,
+                Source::Inline("1".to_string()),
+                Source::File("b.lox".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_help() {
+        assert_eq!(parse(&["parse", "--help"]).unwrap().command, Command::Help(Some("parse".to_string())));
+        assert_eq!(parse(&["help", "tokenize"]).unwrap().command, Command::Help(Some("tokenize".to_string())));
+        assert_eq!(parse(&["-h"]).unwrap().command, Command::Help(None));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        assert!(parse(&["run", "a.lox"]).is_err());
+        assert!(parse(&["tokenize"]).is_err());
+        assert!(parse(&["evaluate", "--optimized", "-e", "1"]).is_err());
+        assert!(parse(&["tokenize", "--format=json", "a.lox"]).is_err());
+        assert!(parse(&["parse", "--format=xml", "a.lox"]).is_err());
+        assert!(parse(&["parse", "--colour=always", "a.lox"]).is_err());
+        assert!(parse(&["parse", "-e"]).is_err());
+        assert!(parse(&["parse", "--max-steps=10", "a.lox"]).is_err());
+        assert!(parse(&["tokenize", "--allow-time", "a.lox"]).is_err());
+        assert!(parse(&["evaluate", "--allow-read", "a.lox"]).is_err());
+        assert!(parse(&["evaluate", "--timeout=soon", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let cli = parse(&["evaluate", "--max-steps=100", "--max-memory=4096", "--max-depth=64", "--timeout=250", "a.lox"]).unwrap();
+        assert_eq!(
+            cli.limits,
+            Limits {
+                max_steps: Some(100),
+                max_memory: Some(4096),
+                max_depth: Some(64),
+                timeout: Some(Duration::from_millis(250)),
+            }
+        );
+        assert_eq!(parse(&["--max-depth=8"]).unwrap().limits.max_depth, Some(8));
+        assert!(parse(&["repl", "--deterministic"]).unwrap().deterministic);
+        assert!(parse(&["parse", "--deterministic", "a.lox"]).is_err());
+        assert!(parse(&["evaluate", "--optimize", "a.lox"]).unwrap().optimize);
+        assert!(parse(&["parse", "--optimize", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn test_capabilities() {
+        assert_eq!(parse(&["evaluate", "a.lox"]).unwrap().capabilities, Capabilities::default());
+        let cli = parse(&["evaluate", "--allow-read=data", "--allow-time", "--allow-read=/tmp", "a.lox"]).unwrap();
+        assert_eq!(
+            cli.capabilities,
+            Capabilities {
+                read: vec![PathBuf::from("data"), PathBuf::from("/tmp")],
+                time: true,
+                ..Capabilities::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+use crate::expr::json_string;
+
+// A tokenizer or parser error, printed as `[line 1] Error at 'x': message`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub location: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn new(line: usize, location: &str, message: &str) -> Self {
+        Diagnostic {
+            line,
+            location: location.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"line\":{},\"location\":{},\"message\":{}}}",
+            self.line,
+            json_string(self.location.trim()),
+            json_string(&self.message)
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error{}: {}", self.line, self.location, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Error)]
+pub enum LoxError {
+    // Every error found while tokenizing or parsing, in source order
+    #[error("{}", lines(.0))]
+    Syntax(Vec<Diagnostic>),
+    #[error("{message}\n[line {line}]")]
+    Runtime { line: usize, message: String },
+    // The sandbox limits set through Interpreter::set_limits; `line` is where evaluation stopped
+    #[error("Step limit of {limit} exceeded.\n[line {line}]")]
+    StepLimit { line: usize, limit: u64 },
+    #[error("Memory limit of {limit} bytes exceeded.\n[line {line}]")]
+    MemoryLimit { line: usize, limit: usize },
+    #[error("Depth limit of {limit} exceeded.\n[line {line}]")]
+    DepthLimit { line: usize, limit: usize },
+    #[error("Timeout of {limit:?} exceeded.\n[line {line}]")]
+    Timeout { line: usize, limit: Duration },
+}
+
+impl LoxError {
+    pub(crate) fn runtime(line: usize, message: &str) -> Self {
+        LoxError::Runtime {
+            line,
+            message: message.to_string(),
+        }
+    }
+
+    // One JSON object per line
+    pub fn to_json(&self) -> String {
+        match self {
+            LoxError::Syntax(diagnostics) => {
+                let objects: Vec<String> = diagnostics.iter().map(Diagnostic::to_json).collect();
+                objects.join("\n")
+            }
+            LoxError::Runtime { line, message } => {
+                format!("{{\"line\":{},\"message\":{}}}", line, json_string(message))
+            }
+            other => {
+                let text = other.to_string();
+                let (message, _) = text.rsplit_once('\n').unwrap_or((&text, ""));
+                format!("{{\"line\":{},\"message\":{}}}", other.line(), json_string(message))
+            }
+        }
+    }
+
+    // Line of the first problem
+    pub fn line(&self) -> usize {
+        match self {
+            LoxError::Syntax(diagnostics) => diagnostics.first().map_or(0, |diagnostic| diagnostic.line),
+            LoxError::Runtime { line, .. }
+            | LoxError::StepLimit { line, .. }
+            | LoxError::MemoryLimit { line, .. }
+            | LoxError::DepthLimit { line, .. }
+            | LoxError::Timeout { line, .. } => *line,
+        }
+    }
+}
+
+fn lines(diagnostics: &[Diagnostic]) -> String {
+    let lines: Vec<String> = diagnostics.iter().map(Diagnostic::to_string).collect();
+    lines.join("\n")
+}
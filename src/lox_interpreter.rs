@@ -0,0 +1,920 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::lox_capabilities::{Capabilities, Capability};
+use crate::expr::{Ast, Binary, Call, ExprId, Get, Grouping, Index, List, Literal, LiteralValue, SetIndex, Slice, Unary, Variable, Visitor};
+use crate::lox_error::LoxError;
+use crate::lox_list::LoxList;
+use crate::token_types::TokenType;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+    Native(Rc<NativeFunction>),
+    List(Rc<LoxList>),
+    Object(Rc<dyn HostObject>),
+}
+
+impl Value {
+    // nil and false are falsey, everything else is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    // What the type() native returns
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "boolean",
+            Value::Nil => "nil",
+            Value::Native(_) => "function",
+            Value::List(_) => "list",
+            Value::Object(_) => "object",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "nil"),
+            Value::Native(_) => write!(f, "<native fn>"),
+            Value::List(list) => write!(f, "{}", list),
+            Value::Object(object) => write!(f, "{} instance", object.type_name()),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(Rc::new(LoxList::new(items.into_iter().map(Into::into).collect())))
+    }
+}
+
+impl TryFrom<&Value> for f64 {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            other => Err(format!("Expected a number but got {}.", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for String {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::String(s) => Ok(s.clone()),
+            other => Err(format!("Expected a string but got {}.", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for bool {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            other => Err(format!("Expected a boolean but got {}.", other)),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Rc<LoxList> {
+    type Error = String;
+
+    fn try_from(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::List(list) => Ok(Rc::clone(list)),
+            other => Err(format!("Expected a list but got {}.", other)),
+        }
+    }
+}
+
+type NativeFn = dyn Fn(&Interpreter, &[Value]) -> Result<Value, String>;
+
+// A Rust closure callable from Lox. The error string becomes a runtime error
+// reported at the line of the call's closing parenthesis
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    // Checked against the interpreter's capabilities before every call
+    pub capability: Option<Capability>,
+    // Bytes a call adds to a value that already exists, like the slot list.push adds;
+    // charged against max_memory before the call, as the return value is charged after it
+    pub(crate) grows: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub(crate) fn new<F>(name: String, arity: usize, function: F) -> Self
+    where
+        F: Fn(&Interpreter, &[Value]) -> Result<Value, String> + 'static,
+    {
+        NativeFunction {
+            name,
+            arity,
+            capability: None,
+            grows: 0,
+            function: Box::new(function),
+        }
+    }
+
+    pub(crate) fn growing(self, bytes: usize) -> Self {
+        NativeFunction { grows: bytes, ..self }
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}/{}>", self.name, self.arity)
+    }
+}
+
+// Functions compare by identity, like in jlox
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl From<&LiteralValue> for Value {
+    fn from(value: &LiteralValue) -> Self {
+        match value {
+            LiteralValue::Number(n) => Value::Number(*n),
+            LiteralValue::String(s) => Value::String(s.clone()),
+            LiteralValue::Bool(b) => Value::Bool(*b),
+            LiteralValue::Nil => Value::Nil,
+        }
+    }
+}
+
+/*
+A Rust value exposed to scripts. `obj.name` reads a property first and otherwise
+looks up a method, so `obj.name(args)` calls it with the arity checked like any
+native function. Methods take `&self`: state they change belongs in a Cell or
+RefCell. Scripts hold the object through an Rc, so it is dropped together with
+the last Lox value referring to it.
+*/
+pub trait HostObject {
+    fn type_name(&self) -> &str;
+
+    fn property(&self, _name: &str) -> Option<Value> {
+        None
+    }
+
+    fn method_arity(&self, _name: &str) -> Option<usize> {
+        None
+    }
+
+    // Only called for names that method_arity accepted, with that many arguments
+    fn call_method(&self, name: &str, args: &[Value]) -> Result<Value, String>;
+}
+
+impl fmt::Debug for dyn HostObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<{} instance>", self.type_name())
+    }
+}
+
+// Objects compare by identity, like jlox instances
+impl PartialEq for dyn HostObject {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+impl<T: HostObject + 'static> From<Rc<T>> for Value {
+    fn from(object: Rc<T>) -> Self {
+        Value::Object(object)
+    }
+}
+
+// Bounds for running untrusted code, applied to each eval or evaluate call on its own.
+// None means unlimited; only max_depth has a default. The deadline is checked between
+// nodes, so a slow native function is not interrupted
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Limits {
+    // Expression nodes evaluated
+    pub max_steps: Option<u64>,
+    // Bytes of string data and list slots created while evaluating, including the slots
+    // list.push and list.insert add
+    pub max_memory: Option<usize>,
+    // Nesting of expressions, both while parsing in eval and while evaluating. Operands of
+    // a flat chain like `1 + 2 + 3` are not nested in each other, so a chain counts once
+    pub max_depth: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+// Parsing and evaluating recurse on the Rust stack, so nesting is bounded unless a host
+// lifts the limit; 128 levels fit in the 2 MiB stack of a spawned thread, even in debug builds
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 128;
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_memory: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            timeout: None,
+        }
+    }
+}
+
+// Tree-walking evaluator; one instance is meant to live as long as a session.
+// Interpreter::default() starts without the standard library
+#[derive(Default)]
+pub struct Interpreter {
+    globals: HashMap<String, Value>,
+    capabilities: Capabilities,
+    limits: Limits,
+    steps: u64,
+    // Nodes evaluated since the interpreter was created; drives the deterministic clock
+    total_steps: u64,
+    deterministic: bool,
+    memory: usize,
+    depth: usize,
+    deadline: Option<Instant>,
+}
+
+impl Interpreter {
+    // An interpreter with the standard library defined as globals
+    pub fn new() -> Self {
+        let mut interpreter = Interpreter::default();
+        crate::lox_stdlib::define_core(&mut interpreter);
+        crate::lox_stdlib::define_io(&mut interpreter);
+        interpreter
+    }
+
+    // Binds `name` to a native function taking exactly `arity` arguments, e.g.
+    // `interpreter.define_native("len", 1, |args| Ok(String::try_from(&args[0])?.len() as f64))`
+    pub fn define_native<R, F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        R: Into<Value>,
+        F: Fn(&[Value]) -> Result<R, String> + 'static,
+    {
+        self.define_restricted_native(name, arity, None, function);
+    }
+
+    // Like define_native, but calls fail with a runtime error unless `capability` was granted
+    pub fn define_restricted_native<R, F>(&mut self, name: &str, arity: usize, capability: Option<Capability>, function: F)
+    where
+        R: Into<Value>,
+        F: Fn(&[Value]) -> Result<R, String> + 'static,
+    {
+        self.define_builtin(name, arity, capability, move |_, args| function(args).map(Into::into));
+    }
+
+    // For the standard library, whose functions also need the interpreter, e.g. for its clock
+    pub(crate) fn define_builtin<F>(&mut self, name: &str, arity: usize, capability: Option<Capability>, function: F)
+    where
+        F: Fn(&Interpreter, &[Value]) -> Result<Value, String> + 'static,
+    {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            capability,
+            grows: 0,
+            function: Box::new(function),
+        };
+        self.define(name, Value::Native(Rc::new(native)));
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name)
+    }
+
+    // Every global sorted by name, so listings are the same on every run
+    pub fn globals(&self) -> Vec<(&str, &Value)> {
+        let mut globals: Vec<(&str, &Value)> = self.globals.iter().map(|(name, value)| (name.as_str(), value)).collect();
+        globals.sort_by_key(|(name, _)| *name);
+        globals
+    }
+
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+    }
+
+    // In deterministic mode the clock is virtual, so a script's output is identical on every run
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    // Seconds since the Unix epoch, or in deterministic mode since the interpreter was
+    // created, counting one microsecond per evaluated node
+    pub fn clock(&self) -> f64 {
+        if self.deterministic {
+            return self.total_steps as f64 / 1_000_000.0;
+        }
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // Tokenizes, parses and evaluates `source` as a single expression
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let ast = self.parse(source)?;
+        self.evaluate(&ast)
+    }
+
+    // Parses under this interpreter's depth limit, for hosts that rewrite the tree before evaluate
+    pub fn parse(&self, source: &str) -> Result<Ast, LoxError> {
+        crate::parse_with_max_depth(source, self.limits.max_depth)
+    }
+
+    pub fn evaluate(&mut self, ast: &Ast) -> Result<Value, LoxError> {
+        self.steps = 0;
+        self.memory = 0;
+        self.depth = 0;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.node(ast, ast.root())
+    }
+
+    // Every child is evaluated through here so the limits are checked once per node
+    fn node(&mut self, ast: &Ast, id: ExprId) -> Result<Value, LoxError> {
+        self.step(ast, id)?;
+        self.depth += 1;
+        let value = ast.accept(id, self);
+        self.depth -= 1;
+        value
+    }
+
+    // Counts a node against the limits without descending into it
+    fn step(&mut self, ast: &Ast, id: ExprId) -> Result<(), LoxError> {
+        self.steps += 1;
+        self.total_steps += 1;
+        if let Some(limit) = self.limits.max_steps.filter(|limit| self.steps > *limit) {
+            return Err(LoxError::StepLimit { line: ast.line(id), limit });
+        }
+        if let Some(limit) = self.limits.max_depth.filter(|limit| self.depth >= *limit) {
+            return Err(LoxError::DepthLimit { line: ast.line(id), limit });
+        }
+        if let (Some(deadline), Some(limit)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() >= deadline {
+                return Err(LoxError::Timeout { line: ast.line(id), limit });
+            }
+        }
+        Ok(())
+    }
+
+    // A list counts the size of a Value per element; the elements were counted when created
+    fn allocate(&mut self, line: usize, value: Value) -> Result<Value, LoxError> {
+        let bytes = match &value {
+            Value::String(s) => s.len(),
+            Value::List(list) => list.len() * mem::size_of::<Value>(),
+            _ => return Ok(value),
+        };
+        self.charge(line, bytes)?;
+        Ok(value)
+    }
+
+    fn charge(&mut self, line: usize, bytes: usize) -> Result<(), LoxError> {
+        self.memory += bytes;
+        if let Some(limit) = self.limits.max_memory.filter(|limit| self.memory > *limit) {
+            return Err(LoxError::MemoryLimit { line, limit });
+        }
+        Ok(())
+    }
+
+    // Evaluates the bound of a slice, with nil standing for one that was left out
+    fn bound(&mut self, ast: &Ast, bound: Option<ExprId>) -> Result<Value, LoxError> {
+        bound.map_or(Ok(Value::Nil), |bound| self.node(ast, bound))
+    }
+
+    // Applies the operator of a binary node to its evaluated operands
+    fn binary(&mut self, expr: &Binary, left: Value, right: Value) -> Result<Value, LoxError> {
+        let line = expr.op.line;
+
+        match (&expr.op.token_type, left, right) {
+            (TokenType::EqualEqual, left, right) => Ok(Value::Bool(left == right)),
+            (TokenType::BangEqual, left, right) => Ok(Value::Bool(left != right)),
+            (TokenType::Plus, Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+            (TokenType::Plus, Value::String(a), Value::String(b)) => self.allocate(line, Value::String(a + &b)),
+            (TokenType::Plus, _, _) => Err(LoxError::runtime(line, "Operands must be two numbers or two strings.")),
+            (op, Value::Number(a), Value::Number(b)) => Ok(match op {
+                TokenType::Minus => Value::Number(a - b),
+                TokenType::Star => Value::Number(a * b),
+                TokenType::Slash => Value::Number(a / b),
+                TokenType::Greater => Value::Bool(a > b),
+                TokenType::GreaterEqual => Value::Bool(a >= b),
+                TokenType::Less => Value::Bool(a < b),
+                TokenType::LessEqual => Value::Bool(a <= b),
+                _ => unreachable!("Not a binary operator: {}", op),
+            }),
+            _ => Err(LoxError::runtime(line, "Operands must be numbers.")),
+        }
+    }
+}
+
+impl Visitor<Result<Value, LoxError>> for Interpreter {
+    // A chain like `1 + 2 + 3` is evaluated in a loop rather than by recursing into its
+    // left side, so it does not count toward max_depth; its operators still count as steps
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, _expr: &Binary) -> Result<Value, LoxError> {
+        let (first, chain) = ast.chain(id);
+        for (inner, _) in chain.iter().rev().skip(1) {
+            self.step(ast, *inner)?;
+        }
+        let mut value = self.node(ast, first)?;
+        for (_, expr) in chain {
+            let right = self.node(ast, expr.right)?;
+            value = self.binary(expr, value, right)?;
+        }
+        Ok(value)
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> Result<Value, LoxError> {
+        Ok(Value::from(&expr.value))
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> Result<Value, LoxError> {
+        self.node(ast, expr.expression)
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> Result<Value, LoxError> {
+        let right = self.node(ast, expr.right)?;
+        match (&expr.op.token_type, right) {
+            (TokenType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+            (TokenType::Minus, _) => Err(LoxError::runtime(expr.op.line, "Operand must be a number.")),
+            (_, right) => Ok(Value::Bool(!right.is_truthy())),
+        }
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> Result<Value, LoxError> {
+        match self.globals.get(&expr.name.lexeme) {
+            Some(value) => Ok(value.clone()),
+            None => Err(LoxError::runtime(
+                expr.name.line,
+                &format!("Undefined variable '{}'.", expr.name.lexeme),
+            )),
+        }
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> Result<Value, LoxError> {
+        let callee = self.node(ast, expr.callee)?;
+        let mut arguments = Vec::with_capacity(expr.arguments.len());
+        for argument in &expr.arguments {
+            arguments.push(self.node(ast, *argument)?);
+        }
+
+        let line = expr.paren.line;
+        let Value::Native(native) = callee else {
+            return Err(LoxError::runtime(line, "Can only call functions and classes."));
+        };
+        if arguments.len() != native.arity {
+            return Err(LoxError::runtime(
+                line,
+                &format!("Expected {} arguments but got {}.", native.arity, arguments.len()),
+            ));
+        }
+        // The virtual clock never reads the host's, so deterministic runs need no time capability
+        let capability = native.capability.filter(|capability| !(self.deterministic && *capability == Capability::Time));
+        if let Some(capability) = capability {
+            self.capabilities
+                .check(&native.name, capability, &arguments)
+                .map_err(|message| LoxError::runtime(line, &message))?;
+        }
+        self.charge(line, native.grows)?;
+        let value = (native.function)(self, &arguments).map_err(|message| LoxError::runtime(line, &message))?;
+        self.allocate(line, value)
+    }
+
+    fn visit_get(&mut self, ast: &Ast, _id: ExprId, expr: &Get) -> Result<Value, LoxError> {
+        let name = &expr.name.lexeme;
+        let object = match self.node(ast, expr.object)? {
+            Value::Object(object) => object,
+            Value::List(list) => {
+                return list
+                    .method(name)
+                    .map(|method| Value::Native(Rc::new(method)))
+                    .ok_or_else(|| LoxError::runtime(expr.name.line, &format!("Undefined property '{}'.", name)));
+            }
+            _ => return Err(LoxError::runtime(expr.name.line, "Only instances have properties.")),
+        };
+        if let Some(value) = object.property(name) {
+            return Ok(value);
+        }
+        let Some(arity) = object.method_arity(name) else {
+            return Err(LoxError::runtime(expr.name.line, &format!("Undefined property '{}'.", name)));
+        };
+
+        // Bind the method to its object so it can be called, or stored and called later
+        let method = name.clone();
+        Ok(Value::Native(Rc::new(NativeFunction::new(
+            format!("{}.{}", object.type_name(), name),
+            arity,
+            move |_, args| object.call_method(&method, args),
+        ))))
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> Result<Value, LoxError> {
+        let mut items = Vec::with_capacity(expr.elements.len());
+        for element in &expr.elements {
+            items.push(self.node(ast, *element)?);
+        }
+        self.allocate(expr.bracket.line, Value::from(items))
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> Result<Value, LoxError> {
+        let object = self.node(ast, expr.object)?;
+        let index = self.node(ast, expr.index)?;
+        let line = expr.bracket.line;
+        let Value::List(list) = object else {
+            return Err(LoxError::runtime(line, "Only lists can be indexed."));
+        };
+        list.get(&index).map_err(|message| LoxError::runtime(line, &message))
+    }
+
+    fn visit_slice(&mut self, ast: &Ast, _id: ExprId, expr: &Slice) -> Result<Value, LoxError> {
+        let object = self.node(ast, expr.object)?;
+        let start = self.bound(ast, expr.start)?;
+        let stop = self.bound(ast, expr.stop)?;
+        let line = expr.bracket.line;
+        let Value::List(list) = object else {
+            return Err(LoxError::runtime(line, "Only lists can be sliced."));
+        };
+        let slice = list.slice(&start, &stop).map_err(|message| LoxError::runtime(line, &message))?;
+        self.allocate(line, Value::List(Rc::new(slice)))
+    }
+
+    // Evaluates to the assigned value, as assignment does in jlox
+    fn visit_set_index(&mut self, ast: &Ast, _id: ExprId, expr: &SetIndex) -> Result<Value, LoxError> {
+        let object = self.node(ast, expr.object)?;
+        let index = self.node(ast, expr.index)?;
+        let value = self.node(ast, expr.value)?;
+        let line = expr.bracket.line;
+        let Value::List(list) = object else {
+            return Err(LoxError::runtime(line, "Only lists can be indexed."));
+        };
+        list.set(&index, value.clone()).map_err(|message| LoxError::runtime(line, &message))?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use crate::expr::ExprEnum;
+    use super::*;
+
+    fn eval(source: &str) -> Result<Value, LoxError> {
+        Interpreter::new().eval(source)
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("(1 + 2) * 3 - 4 / 8"), Ok(Value::Number(8.5)));
+        assert_eq!(eval("-(3)"), Ok(Value::Number(-3.0)));
+        assert_eq!(eval("\"foo\" + \"bar\""), Ok(Value::String("foobar".to_string())));
+    }
+
+    #[test]
+    fn test_comparison_and_equality() {
+        assert_eq!(eval("1 < 2 == 2 >= 3"), Ok(Value::Bool(false)));
+        assert_eq!(eval("nil == nil"), Ok(Value::Bool(true)));
+        assert_eq!(eval("\"1\" == 1"), Ok(Value::Bool(false)));
+        assert_eq!(eval("!nil != !0"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(eval("10 / 4").unwrap().to_string(), "2.5");
+        assert_eq!(eval("3 * 2").unwrap().to_string(), "6");
+        assert_eq!(eval("\"a\"").unwrap().to_string(), "a");
+        assert_eq!(eval("nil").unwrap().to_string(), "nil");
+    }
+
+    #[test]
+    fn test_number_formatting_is_fixed() {
+        assert_eq!(eval("0.1 + 0.2").unwrap().to_string(), "0.30000000000000004");
+        assert_eq!(eval("1000000 * 1000000 * 1000000 * 1000").unwrap().to_string(), "1000000000000000000000");
+        assert_eq!(eval("-0").unwrap().to_string(), "-0");
+        assert_eq!(eval("1 / 3").unwrap().to_string(), "0.3333333333333333");
+    }
+
+    #[test]
+    fn test_deterministic_clock() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_deterministic(true);
+        assert_eq!(interpreter.clock(), 0.0);
+        interpreter.eval("1 + 2").unwrap();
+        assert_eq!(interpreter.clock(), 0.000003);
+        interpreter.eval("-1").unwrap();
+        assert_eq!(interpreter.clock(), 0.000005);
+
+        interpreter.set_deterministic(false);
+        assert!(interpreter.clock() > 1_000_000_000.0);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert_eq!(eval("\"a\"\n- 1"), Err(LoxError::runtime(2, "Operands must be numbers.")));
+        assert_eq!(eval("-\"a\""), Err(LoxError::runtime(1, "Operand must be a number.")));
+        assert_eq!(eval("true + 1"), Err(LoxError::runtime(1, "Operands must be two numbers or two strings.")));
+        assert_eq!(eval("x"), Err(LoxError::runtime(1, "Undefined variable 'x'.")));
+        assert_eq!(eval("-\"a\"").unwrap_err().to_string(), "Operand must be a number.\n[line 1]");
+    }
+
+    #[test]
+    fn test_native_functions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("len", 1, |args| Ok(String::try_from(&args[0])?.len() as f64));
+        interpreter.define_native("max", 2, |args| Ok(f64::try_from(&args[0])?.max(f64::try_from(&args[1])?)));
+        interpreter.define_native("shout", 1, |args| Ok(String::try_from(&args[0])?.to_uppercase() + "!"));
+        interpreter.define_native("nothing", 0, |_| Ok(()));
+        interpreter.define("answer", Value::Number(42.0));
+
+        assert_eq!(interpreter.eval("len(\"abc\") + max(1, answer)"), Ok(Value::Number(45.0)));
+        assert_eq!(interpreter.eval("shout(\"hi\")"), Ok(Value::String("HI!".to_string())));
+        assert_eq!(interpreter.eval("nothing()"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("len == len"), Ok(Value::Bool(true)));
+        assert_eq!(interpreter.eval("len == max"), Ok(Value::Bool(false)));
+        assert_eq!(interpreter.eval("len").unwrap().to_string(), "<native fn>");
+    }
+
+    #[test]
+    fn test_restricted_natives() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_restricted_native("now", 0, Some(Capability::Time), |_| Ok(1.0));
+        assert_eq!(interpreter.eval("now()"), Err(LoxError::runtime(1, "now() needs the 'time' capability.")));
+        interpreter.set_capabilities(Capabilities { time: true, ..Capabilities::default() });
+        assert_eq!(interpreter.eval("now()"), Ok(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn test_native_function_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("len", 1, |args| Ok(String::try_from(&args[0])?.len() as f64));
+
+        assert_eq!(interpreter.eval("len(\"a\",\n2)"), Err(LoxError::runtime(2, "Expected 1 arguments but got 2.")));
+        assert_eq!(interpreter.eval("len(\n1\n)"), Err(LoxError::runtime(3, "Expected a string but got 1.")));
+        assert_eq!(interpreter.eval("\"len\"()"), Err(LoxError::runtime(1, "Can only call functions and classes.")));
+        assert_eq!(interpreter.eval("len(x)"), Err(LoxError::runtime(1, "Undefined variable 'x'.")));
+    }
+
+    struct Counter {
+        count: Cell<f64>,
+        dropped: Rc<Cell<bool>>,
+    }
+
+    impl HostObject for Counter {
+        fn type_name(&self) -> &str {
+            "Counter"
+        }
+
+        fn property(&self, name: &str) -> Option<Value> {
+            match name {
+                "count" => Some(Value::Number(self.count.get())),
+                _ => None,
+            }
+        }
+
+        fn method_arity(&self, name: &str) -> Option<usize> {
+            match name {
+                "add" => Some(1),
+                _ => None,
+            }
+        }
+
+        fn call_method(&self, _name: &str, args: &[Value]) -> Result<Value, String> {
+            self.count.set(self.count.get() + f64::try_from(&args[0])?);
+            Ok(Value::Number(self.count.get()))
+        }
+    }
+
+    impl Drop for Counter {
+        fn drop(&mut self) {
+            self.dropped.set(true);
+        }
+    }
+
+    #[test]
+    fn test_host_objects() {
+        let dropped = Rc::new(Cell::new(false));
+        let counter = Rc::new(Counter { count: Cell::new(0.0), dropped: dropped.clone() });
+        let mut interpreter = Interpreter::new();
+        interpreter.define("counter", counter.clone().into());
+
+        assert_eq!(interpreter.eval("counter.add(2) + counter.add(3)"), Ok(Value::Number(7.0)));
+        assert_eq!(interpreter.eval("counter.count"), Ok(Value::Number(5.0)));
+        assert_eq!(counter.count.get(), 5.0);
+        assert_eq!(interpreter.eval("counter == counter"), Ok(Value::Bool(true)));
+        assert_eq!(interpreter.eval("counter").unwrap().to_string(), "Counter instance");
+        assert_eq!(interpreter.eval("counter.add").unwrap().to_string(), "<native fn>");
+
+        assert_eq!(interpreter.eval("counter.size"), Err(LoxError::runtime(1, "Undefined property 'size'.")));
+        assert_eq!(interpreter.eval("counter.add()"), Err(LoxError::runtime(1, "Expected 1 arguments but got 0.")));
+        assert_eq!(interpreter.eval("counter.add(nil)"), Err(LoxError::runtime(1, "Expected a number but got nil.")));
+        assert_eq!(interpreter.eval("1.count"), Err(LoxError::runtime(1, "Only instances have properties.")));
+
+        drop(counter);
+        assert!(!dropped.get());
+        drop(interpreter);
+        assert!(dropped.get());
+    }
+
+    fn list(items: &[f64]) -> Value {
+        Value::from(items.to_vec())
+    }
+
+    #[test]
+    fn test_list_indexing() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define("a", list(&[1.0, 2.0, 3.0]));
+        assert_eq!(interpreter.eval("[1, \"b\", [nil]]").unwrap().to_string(), "[1, \"b\", [nil]]");
+        assert_eq!(interpreter.eval("a[0] + a[-1]"), Ok(Value::Number(4.0)));
+        assert_eq!(interpreter.eval("a[1] = a[2] * 10"), Ok(Value::Number(30.0)));
+        assert_eq!(interpreter.eval("a").unwrap().to_string(), "[1, 30, 3]");
+        assert_eq!(interpreter.eval("a == a"), Ok(Value::Bool(true)));
+        assert_eq!(interpreter.eval("[] == []"), Ok(Value::Bool(false)));
+
+        assert_eq!(interpreter.eval("a[3]"), Err(LoxError::runtime(1, "Index 3 is out of range for a list of length 3.")));
+        assert_eq!(interpreter.eval("a[-4] = 0"), Err(LoxError::runtime(1, "Index -4 is out of range for a list of length 3.")));
+        assert_eq!(interpreter.eval("a[0.5]"), Err(LoxError::runtime(1, "List index must be an integer but got 0.5.")));
+        assert_eq!(interpreter.eval("\"abc\"\n[0]"), Err(LoxError::runtime(2, "Only lists can be indexed.")));
+    }
+
+    #[test]
+    fn test_list_slices() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define("a", list(&[0.0, 1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(interpreter.eval("a[1:3]").unwrap().to_string(), "[1, 2]");
+        assert_eq!(interpreter.eval("a[:2]").unwrap().to_string(), "[0, 1]");
+        assert_eq!(interpreter.eval("a[-2:]").unwrap().to_string(), "[3, 4]");
+        assert_eq!(interpreter.eval("a[3:1]").unwrap().to_string(), "[]");
+        assert_eq!(interpreter.eval("a[-10:10]").unwrap().to_string(), "[0, 1, 2, 3, 4]");
+        assert_eq!(interpreter.eval("a[:] == a"), Ok(Value::Bool(false)));
+        assert_eq!(interpreter.eval("a[\"x\":]"), Err(LoxError::runtime(1, "Slice bounds must be integers but got x.")));
+        assert_eq!(interpreter.eval("nil[:]"), Err(LoxError::runtime(1, "Only lists can be sliced.")));
+    }
+
+    #[test]
+    fn test_list_methods() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define("a", list(&[1.0]));
+        interpreter.define("b", list(&[]));
+        assert_eq!(interpreter.eval("a.push(2)"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("a.insert(0, 0)"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("a.insert(a.len(), 3)"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("a").unwrap().to_string(), "[0, 1, 2, 3]");
+        assert_eq!(interpreter.eval("a.remove(1) + a.pop()"), Ok(Value::Number(4.0)));
+        assert_eq!(interpreter.eval("a").unwrap().to_string(), "[0, 2]");
+
+        assert_eq!(interpreter.eval("b.pop()"), Err(LoxError::runtime(1, "Cannot pop from an empty list.")));
+        assert_eq!(interpreter.eval("b.remove(0)"), Err(LoxError::runtime(1, "Index 0 is out of range for a list of length 0.")));
+        assert_eq!(interpreter.eval("b.insert(1, 0)"), Err(LoxError::runtime(1, "Index 1 is out of range for a list of length 0.")));
+        assert_eq!(interpreter.eval("b.sort"), Err(LoxError::runtime(1, "Undefined property 'sort'.")));
+
+        // A list holding itself still prints
+        assert_eq!(interpreter.eval("b.push(b)"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("b").unwrap().to_string(), "[[...]]");
+        assert_eq!(interpreter.eval("b.pop() == b"), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits { max_steps: Some(5), ..Limits::default() });
+        assert_eq!(interpreter.eval("1 + 2 * 3"), Ok(Value::Number(7.0)));
+        // The budget is per call, not per interpreter
+        assert_eq!(interpreter.eval("1 + 2 * 3"), Ok(Value::Number(7.0)));
+        assert_eq!(interpreter.eval("1 +\n2 * 3 - 4"), Err(LoxError::StepLimit { line: 2, limit: 5 }));
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("repeat", 1, |args| Ok("ab".repeat(f64::try_from(&args[0])? as usize)));
+        interpreter.set_limits(Limits { max_memory: Some(8), ..Limits::default() });
+        assert_eq!(interpreter.eval("\"abcd\" + \"efgh\""), Ok(Value::String("abcdefgh".to_string())));
+        assert_eq!(interpreter.eval("\"ab\" + \"cd\" + \"ef\""), Err(LoxError::MemoryLimit { line: 1, limit: 8 }));
+        assert_eq!(interpreter.eval("repeat(4)"), Ok(Value::String("abababab".to_string())));
+        assert_eq!(interpreter.eval("repeat(5)"), Err(LoxError::MemoryLimit { line: 1, limit: 8 }));
+
+        let slot = mem::size_of::<Value>();
+        interpreter.set_limits(Limits { max_memory: Some(2 * slot), ..Limits::default() });
+        assert_eq!(interpreter.eval("[1, 2]").unwrap().to_string(), "[1, 2]");
+        assert_eq!(interpreter.eval("[1, 2][:]"), Err(LoxError::MemoryLimit { line: 1, limit: 2 * slot }));
+
+        // Growing a list is charged before it happens
+        let list = Rc::new(LoxList::default());
+        interpreter.define("xs", Value::List(Rc::clone(&list)));
+        assert_eq!(interpreter.eval("xs.push(1) == xs.push(2)"), Ok(Value::Bool(true)));
+        assert_eq!(interpreter.eval("xs.push(1) == xs.push(2) == xs.insert(0, 3)"), Err(LoxError::MemoryLimit { line: 1, limit: 2 * slot }));
+        assert_eq!(list.len(), 4);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits { max_depth: Some(50), ..Limits::default() });
+        let chain = vec!["1"; 100].join(" + ");
+        assert_eq!(interpreter.eval(&chain), Ok(Value::Number(100.0)));
+        assert!(matches!(interpreter.eval(&format!("{}1{}", "(".repeat(100), ")".repeat(100))), Err(LoxError::Syntax(_))));
+
+        // Nested to the right, so deep enough to overflow the stack without a limit
+        let mut ast = Ast::default();
+        let plus = crate::token::Token::new(TokenType::Plus, "+".to_string(), None, 1);
+        let one = ast.add(ExprEnum::Literal(Literal { value: LiteralValue::Number(1.0), line: 1 }));
+        ast.root = one;
+        for _ in 0..1_000_000 {
+            ast.root = ast.add(ExprEnum::Binary(Binary { left: one, op: plus.clone(), right: ast.root }));
+        }
+        interpreter.set_limits(Limits { max_depth: Some(1000), ..Limits::default() });
+        assert_eq!(interpreter.evaluate(&ast), Err(LoxError::DepthLimit { line: 1, limit: 1000 }));
+    }
+
+    #[test]
+    fn test_default_depth_limit() {
+        let mut interpreter = Interpreter::new();
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(interpreter.eval(&nested(DEFAULT_MAX_DEPTH - 1)), Ok(Value::Number(1.0)));
+        assert!(matches!(interpreter.eval(&nested(DEFAULT_MAX_DEPTH)), Err(LoxError::Syntax(_))));
+        // Flat chains are evaluated in a loop, however long
+        let chain = vec!["1"; 100_000].join(" + ");
+        assert_eq!(interpreter.eval(&chain), Ok(Value::Number(100_000.0)));
+        let chain = format!("{} - 1 * 2", vec!["(1)"; 1000].join(" + "));
+        assert_eq!(interpreter.eval(&chain), Ok(Value::Number(998.0)));
+    }
+
+    #[test]
+    fn test_chains_count_every_operator_as_a_step() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits { max_steps: Some(9), ..Limits::default() });
+        assert_eq!(interpreter.eval("1 + 2 + 3 + 4 + 5"), Ok(Value::Number(15.0)));
+        assert_eq!(interpreter.eval("1 + 2 + 3 + 4 + 5 + 6"), Err(LoxError::StepLimit { line: 1, limit: 9 }));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("nap", 0, |_| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(())
+        });
+        interpreter.set_limits(Limits { timeout: Some(Duration::from_millis(10)), ..Limits::default() });
+        assert_eq!(interpreter.eval("nap()"), Ok(Value::Nil));
+        let err = interpreter.eval("nap() ==\nnap()").unwrap_err();
+        assert_eq!(err, LoxError::Timeout { line: 2, limit: Duration::from_millis(10) });
+        assert_eq!(err.to_string(), "Timeout of 10ms exceeded.\n[line 2]");
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let err = eval("(1 +").unwrap_err();
+        assert_eq!(err.to_string(), "[line 1] Error at end: Expect expression.\n[line 1] Error at end: Expect ')' after expression.");
+        assert!(matches!(eval("@"), Err(LoxError::Syntax(_))));
+    }
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use crate::lox_interpreter::{NativeFunction, Value};
+
+/*
+The runtime value of a list literal. Lists are shared, so `a.push(1)` is seen
+through every variable holding `a`, and compare by identity like objects.
+Indexes may be negative to count from the end; slices clamp their bounds and
+always return a new list.
+*/
+#[derive(Default)]
+pub struct LoxList {
+    items: RefCell<Vec<Value>>,
+}
+
+impl LoxList {
+    pub fn new(items: Vec<Value>) -> Self {
+        LoxList { items: RefCell::new(items) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.items.borrow().clone()
+    }
+
+    pub(crate) fn get(&self, index: &Value) -> Result<Value, String> {
+        let position = self.position(index, self.len())?;
+        Ok(self.items.borrow()[position].clone())
+    }
+
+    pub(crate) fn set(&self, index: &Value, value: Value) -> Result<(), String> {
+        let position = self.position(index, self.len())?;
+        self.items.borrow_mut()[position] = value;
+        Ok(())
+    }
+
+    pub(crate) fn slice(&self, start: &Value, stop: &Value) -> Result<LoxList, String> {
+        let items = self.items.borrow();
+        let start = bound(start, items.len(), 0)?;
+        let stop = bound(stop, items.len(), items.len())?.max(start);
+        Ok(LoxList::new(items[start..stop].to_vec()))
+    }
+
+    // The list methods, bound to this list like methods of a host object
+    pub(crate) fn method(self: &Rc<Self>, name: &str) -> Option<NativeFunction> {
+        let list = Rc::clone(self);
+        let name = format!("list.{}", name);
+        let method = match name.as_str() {
+            "list.push" => NativeFunction::new(name, 1, move |_, args| {
+                list.items.borrow_mut().push(args[0].clone());
+                Ok(Value::Nil)
+            })
+            .growing(mem::size_of::<Value>()),
+            "list.pop" => NativeFunction::new(name, 0, move |_, _| {
+                list.items.borrow_mut().pop().ok_or_else(|| "Cannot pop from an empty list.".to_string())
+            }),
+            "list.len" => NativeFunction::new(name, 0, move |_, _| Ok(Value::Number(list.len() as f64))),
+            // The index may equal the length, which appends
+            "list.insert" => NativeFunction::new(name, 2, move |_, args| {
+                let position = list.position(&args[0], list.len() + 1)?;
+                list.items.borrow_mut().insert(position, args[1].clone());
+                Ok(Value::Nil)
+            })
+            .growing(mem::size_of::<Value>()),
+            "list.remove" => NativeFunction::new(name, 1, move |_, args| {
+                let position = list.position(&args[0], list.len())?;
+                Ok(list.items.borrow_mut().remove(position))
+            }),
+            _ => return None,
+        };
+        Some(method)
+    }
+
+    // Resolves a possibly negative index against `limit`, the number of valid positions
+    fn position(&self, index: &Value, limit: usize) -> Result<usize, String> {
+        let n = match index {
+            Value::Number(n) if n.fract() == 0.0 => *n,
+            other => return Err(format!("List index must be an integer but got {}.", other)),
+        };
+        let position = if n < 0.0 { n + limit as f64 } else { n };
+        if position < 0.0 || position >= limit as f64 {
+            return Err(format!("Index {} is out of range for a list of length {}.", index, self.len()));
+        }
+        Ok(position as usize)
+    }
+
+    // Strings inside a list are quoted, and a list containing itself prints as [...]
+    fn write(&self, f: &mut fmt::Formatter, open: &mut Vec<*const LoxList>) -> fmt::Result {
+        if open.contains(&(self as *const LoxList)) {
+            return write!(f, "[...]");
+        }
+        open.push(self);
+        write!(f, "[")?;
+        for (i, item) in self.items.borrow().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            match item {
+                Value::String(s) => write!(f, "\"{}\"", s)?,
+                Value::List(list) => list.write(f, open)?,
+                other => write!(f, "{}", other)?,
+            }
+        }
+        open.pop();
+        write!(f, "]")
+    }
+}
+
+// A missing or nil bound is the start or end of the list; others are clamped to it
+fn bound(value: &Value, len: usize, missing: usize) -> Result<usize, String> {
+    match value {
+        Value::Nil => Ok(missing),
+        Value::Number(n) if n.fract() == 0.0 => {
+            let position = if *n < 0.0 { n + len as f64 } else { *n };
+            Ok(position.clamp(0.0, len as f64) as usize)
+        }
+        other => Err(format!("Slice bounds must be integers but got {}.", other)),
+    }
+}
+
+impl fmt::Display for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+impl fmt::Debug for LoxList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// Lists compare by identity, like objects
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
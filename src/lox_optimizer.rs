@@ -0,0 +1,158 @@
+use crate::expr::{Ast, Binary, ExprEnum, ExprId, Folder, Grouping, Literal, LiteralValue, Unary};
+use crate::token_types::TokenType;
+
+/*
+Folds constant subtrees and strips groupings, which only matter to the parser.
+Operations that would fail at runtime ("a" - 1, -nil, ...) are left untouched, so
+the error is still raised with the operator's line. Divisions by zero are kept too,
+as their infinite or NaN result has no literal syntax for the printers to show.
+In the operand of `!` only truthiness matters, so `!!!x` becomes `!x`.
+*/
+pub struct LoxOptimizer;
+
+impl LoxOptimizer {
+    pub fn optimize(ast: Ast) -> Ast {
+        ast.fold(&mut LoxOptimizer)
+    }
+}
+
+impl Folder for LoxOptimizer {
+    fn fold_binary(&mut self, ast: &mut Ast, expr: Binary) -> ExprId {
+        let folded = match (literal(ast, expr.left), literal(ast, expr.right)) {
+            (Some(left), Some(right)) => fold_binary(&expr.op.token_type, left, right),
+            _ => None,
+        };
+        match folded {
+            Some(value) => ast.add(ExprEnum::Literal(Literal { value, line: expr.op.line })),
+            None => ast.add(ExprEnum::Binary(expr)),
+        }
+    }
+
+    fn fold_grouping(&mut self, _ast: &mut Ast, expr: Grouping) -> ExprId {
+        expr.expression
+    }
+
+    fn fold_unary(&mut self, ast: &mut Ast, expr: Unary) -> ExprId {
+        let folded = match (&expr.op.token_type, literal(ast, expr.right)) {
+            (TokenType::Minus, Some(LiteralValue::Number(n))) => Some(LiteralValue::Number(-n)),
+            (TokenType::Bang, Some(value)) => Some(LiteralValue::Bool(!is_truthy(value))),
+            _ => None,
+        };
+        if let Some(value) = folded {
+            return ast.add(ExprEnum::Literal(Literal { value, line: expr.op.line }));
+        }
+
+        // `!(!!y)` only depends on the truthiness of `y`, so it is the inner `!y`
+        if expr.op.token_type == TokenType::Bang {
+            if let Some(inner) = negation(ast, expr.right) {
+                if negation(ast, inner).is_some() {
+                    return inner;
+                }
+            }
+        }
+        ast.add(ExprEnum::Unary(expr))
+    }
+}
+
+fn negation(ast: &Ast, id: ExprId) -> Option<ExprId> {
+    match &ast[id] {
+        ExprEnum::Unary(unary) if unary.op.token_type == TokenType::Bang => Some(unary.right),
+        _ => None,
+    }
+}
+
+fn literal(ast: &Ast, id: ExprId) -> Option<&LiteralValue> {
+    match &ast[id] {
+        ExprEnum::Literal(literal) => Some(&literal.value),
+        _ => None,
+    }
+}
+
+fn fold_binary(op: &TokenType, left: &LiteralValue, right: &LiteralValue) -> Option<LiteralValue> {
+    use LiteralValue::{Bool, Number};
+
+    match (op, left, right) {
+        (TokenType::EqualEqual, _, _) => Some(Bool(left == right)),
+        (TokenType::BangEqual, _, _) => Some(Bool(left != right)),
+        (TokenType::Plus, LiteralValue::String(a), LiteralValue::String(b)) => {
+            Some(LiteralValue::String(format!("{}{}", a, b)))
+        }
+        (TokenType::Slash, Number(_), Number(b)) if *b == 0.0 => None,
+        (_, Number(a), Number(b)) => match op {
+            TokenType::Plus => Some(Number(a + b)),
+            TokenType::Minus => Some(Number(a - b)),
+            TokenType::Star => Some(Number(a * b)),
+            TokenType::Slash => Some(Number(a / b)),
+            TokenType::Greater => Some(Bool(a > b)),
+            TokenType::GreaterEqual => Some(Bool(a >= b)),
+            TokenType::Less => Some(Bool(a < b)),
+            TokenType::LessEqual => Some(Bool(a <= b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_truthy(value: &LiteralValue) -> bool {
+    !matches!(value, LiteralValue::Nil | LiteralValue::Bool(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::AstPrinter;
+    use crate::lox_parser::LoxParser;
+    use crate::lox_tokenizer::LoxTokenizer;
+
+    fn optimize(source: &str) -> String {
+        let mut tokenizer = LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize(source));
+        let ast = LoxOptimizer::optimize(parser.parse());
+        ast.accept(ast.root, &mut AstPrinter {})
+    }
+
+    #[test]
+    fn test_folds_arithmetic() {
+        assert_eq!(optimize("(1 + 2) * 3"), "9.0");
+        assert_eq!(optimize("-(10 / 4) + a"), "(+ -2.5 a)");
+        assert_eq!(optimize("\"foo\" + \"bar\""), "foobar");
+    }
+
+    #[test]
+    fn test_folds_comparison_and_equality() {
+        assert_eq!(optimize("1 < 2 == !nil"), "true");
+        assert_eq!(optimize("\"1\" == 1"), "false");
+        assert_eq!(optimize("nil != nil"), "false");
+    }
+
+    #[test]
+    fn test_strips_groupings() {
+        assert_eq!(optimize("((a)) * (b - c)"), "(* a (- b c))");
+    }
+
+    #[test]
+    fn test_keeps_runtime_errors() {
+        assert_eq!(optimize("\"a\" - 1"), "(- a 1.0)");
+        assert_eq!(optimize("-\"a\" + (1 + 2)"), "(+ (- a) 3.0)");
+        assert_eq!(optimize("1 / 0"), "(/ 1.0 0.0)");
+        assert_eq!(optimize("true + 1"), "(+ true 1.0)");
+    }
+
+    #[test]
+    fn test_simplifies_double_negation_in_boolean_context() {
+        assert_eq!(optimize("!!!x"), "(! x)");
+        assert_eq!(optimize("!(!(!x))"), "(! x)");
+        assert_eq!(optimize("!!x"), "(! (! x))");
+    }
+
+    #[test]
+    fn test_folded_literal_keeps_operator_line() {
+        let mut tokenizer = LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize("1\n+\n2"));
+        let ast = LoxOptimizer::optimize(parser.parse());
+        match &ast[ast.root] {
+            ExprEnum::Literal(literal) => assert_eq!(literal.line, 2),
+            other => panic!("Expected literal, got {:?}", other),
+        }
+    }
+}
@@ -1,55 +1,157 @@
-use TokenType::{Bang, BangEqual, EqualEqual, False, Greater, GreaterEqual, Identifier, LeftParen, Less, LessEqual, Minus, Nil, Number, Plus, Slash, Star, True};
-use crate::expr::ExprEnum;
-use crate::expr::{Binary, Grouping, Literal, Unary};
+use TokenType::{Bang, BangEqual, Colon, Comma, Dot, Equal, EqualEqual, False, Greater, GreaterEqual, Identifier, LeftBracket, LeftParen, Less, LessEqual, Minus, Nil, Number, Plus, RightBracket, RightParen, Slash, Star, True};
+use crate::expr::{Ast, ExprEnum, ExprId};
+use crate::lox_error::Diagnostic;
+use crate::expr::{Binary, Call, Get, Grouping, Index, List, Literal, LiteralValue, SetIndex, Slice, Unary, Variable};
 use crate::token::Token;
 use crate::token_types::TokenType;
 
 pub(crate) struct LoxParser {
     tokens: Vec<Token>,
     current: usize,
+    ast: Ast,
     pub(crate) has_error: bool,
+    pub(crate) errors: Vec<Diagnostic>,
+    // Bounds the recursion of expression and unary, and the length of postfix chains, so
+    // hostile input cannot overflow the stack
+    pub(crate) max_depth: Option<usize>,
+    depth: usize,
+    // Set once the depth limit is hit; the remaining input is skipped without further errors
+    abandoned: bool,
+}
+
+// Binding strength of each grammar level below, from loosest to tightest
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub(crate) enum Precedence {
+    Assignment,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Primary,
+}
+
+impl Precedence {
+    pub(crate) fn of_binary(token_type: &TokenType) -> Precedence {
+        match token_type {
+            BangEqual | EqualEqual => Precedence::Equality,
+            Greater | GreaterEqual | Less | LessEqual => Precedence::Comparison,
+            Minus | Plus => Precedence::Term,
+            Slash | Star => Precedence::Factor,
+            _ => panic!("Not a binary operator: {}", token_type),
+        }
+    }
+
+    pub(crate) fn next(self) -> Precedence {
+        match self {
+            Precedence::Assignment => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary | Precedence::Primary => Precedence::Primary,
+        }
+    }
 }
 
 /*
-expression     → equality ;
+expression     → assignment ;
+assignment     → call "[" expression "]" "=" assignment
+| equality ;
 equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
 term           → factor ( ( "-" | "+" ) factor )* ;
 factor         → unary ( ( "/" | "*" ) unary )* ;
 unary          → ( "!" | "-" ) unary
-| primary ;
+| call ;
+call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" subscript "]" )* ;
+arguments      → expression ( "," expression )* ;
+subscript      → expression | expression? ":" expression? ;
 primary        → NUMBER | STRING | "true" | "false" | "nil"
-| "(" expression ")" ;
+| "(" expression ")" | IDENTIFIER
+| "[" ( expression ( "," expression )* )? "]" ;
 */
 impl LoxParser {
     pub(crate) fn new(tokens: Vec<Token>) -> Self {
         LoxParser {
             tokens,
             current: 0,
+            ast: Ast::default(),
             has_error: false,
+            errors: Vec::new(),
+            max_depth: None,
+            depth: 0,
+            abandoned: false,
         }
     }
 
-    pub(crate) fn parse(&mut self) -> Box<ExprEnum> {
+    pub(crate) fn parse(&mut self) -> Ast {
         let expr = self.expression();
-        if self.has_error {
-            return Box::new(ExprEnum::None);
+        if !self.has_error && !self.is_at_end() {
+            self.error(self.peek(), "Expect end of expression.");
+        }
+        self.ast.root = if self.has_error {
+            self.ast.add(ExprEnum::None)
+        } else {
+            expr
+        };
+        std::mem::take(&mut self.ast)
+    }
+
+    fn expression(&mut self) -> ExprId {
+        self.nested(Self::assignment)
+    }
+
+    fn assignment(&mut self) -> ExprId {
+        let expr = self.equality();
+
+        if self.match_tokens(vec![Equal]) {
+            let equals = self.previous();
+            let value = self.nested(Self::assignment);
+            if let ExprEnum::Index(target) = &self.ast[expr] {
+                let target = target.clone();
+                return self.ast.add(ExprEnum::SetIndex(SetIndex {
+                    object: target.object,
+                    bracket: target.bracket,
+                    index: target.index,
+                    value,
+                }));
+            }
+            self.error(equals, "Invalid assignment target.");
         }
+
+        expr
+    }
+
+    fn nested(&mut self, production: fn(&mut Self) -> ExprId) -> ExprId {
+        if self.exceeds_depth() {
+            return self.ast.add(ExprEnum::None);
+        }
+        self.depth += 1;
+        let expr = production(self);
+        self.depth -= 1;
         expr
     }
 
-    fn expression(&mut self) -> Box<ExprEnum> {
-        self.equality()
+    // Reports reaching the depth limit at the next token and skips the rest of the input
+    fn exceeds_depth(&mut self) -> bool {
+        let Some(max) = self.max_depth.filter(|max| self.depth >= *max) else {
+            return false;
+        };
+        self.error(self.peek(), &format!("Expression nesting exceeds the depth limit of {}.", max));
+        self.current = self.tokens.len() - 1;
+        self.abandoned = true;
+        true
     }
 
 
-    fn equality(&mut self) -> Box<ExprEnum> {
+    fn equality(&mut self) -> ExprId {
         let mut expr = self.comparison();
 
         while self.match_tokens(vec![BangEqual, EqualEqual]) {
             let operator = self.previous();
             let right = self.comparison();
-            expr = Box::from(ExprEnum::Binary(Binary {
+            expr = self.ast.add(ExprEnum::Binary(Binary {
                 left: expr,
                 op: operator,
                 right,
@@ -59,13 +161,13 @@ impl LoxParser {
         expr
     }
 
-    fn comparison(&mut self) -> Box<ExprEnum> {
+    fn comparison(&mut self) -> ExprId {
         let mut expr = self.term();
 
         while self.match_tokens(vec![Greater, GreaterEqual, Less, LessEqual]) {
             let operator = self.previous();
             let right = self.term();
-            expr = Box::new(ExprEnum::Binary(Binary {
+            expr = self.ast.add(ExprEnum::Binary(Binary {
                 left: expr,
                 op: operator,
                 right,
@@ -75,13 +177,13 @@ impl LoxParser {
         expr
     }
 
-    fn term(&mut self) -> Box<ExprEnum> {
+    fn term(&mut self) -> ExprId {
         let mut expr = self.factor();
 
         while self.match_tokens(vec![Minus, Plus]) {
             let operator = self.previous();
             let right = self.factor();
-            expr = Box::new(ExprEnum::Binary(Binary {
+            expr = self.ast.add(ExprEnum::Binary(Binary {
                 left: expr,
                 op: operator,
                 right,
@@ -91,13 +193,13 @@ impl LoxParser {
         expr
     }
 
-    fn factor(&mut self) -> Box<ExprEnum> {
+    fn factor(&mut self) -> ExprId {
         let mut expr = self.unary();
 
         while self.match_tokens(vec![Slash, Star]) {
             let operator = self.previous();
             let right = self.unary();
-            expr = Box::new(ExprEnum::Binary(Binary {
+            expr = self.ast.add(ExprEnum::Binary(Binary {
                 left: expr,
                 op: operator,
                 right,
@@ -107,58 +209,152 @@ impl LoxParser {
         expr
     }
 
-    fn unary(&mut self) -> Box<ExprEnum> {
+    fn unary(&mut self) -> ExprId {
         if self.match_tokens(vec![Bang, Minus]) {
             let operator = self.previous();
-            let right = self.unary();
-            return Box::new(ExprEnum::Unary(Unary {
+            let right = self.nested(Self::unary);
+            return self.ast.add(ExprEnum::Unary(Unary {
                 op: operator,
                 right,
             }));
         }
 
-        self.primary()
+        self.call()
+    }
+
+    // Each postfix operator nests the expression before it one level deeper, so a long
+    // chain like `f()()()` is bounded like parentheses are
+    fn call(&mut self) -> ExprId {
+        let mut expr = self.primary();
+        let depth = self.depth;
+
+        loop {
+            if [LeftParen, LeftBracket, Dot].into_iter().any(|token_type| self.match_token(token_type)) && self.exceeds_depth() {
+                expr = self.ast.add(ExprEnum::None);
+                break;
+            }
+            self.depth += 1;
+            if self.match_tokens(vec![LeftParen]) {
+                expr = self.finish_call(expr);
+            } else if self.match_tokens(vec![LeftBracket]) {
+                expr = self.subscript(expr);
+            } else if self.match_tokens(vec![Dot]) {
+                let name = self.peek();
+                self.consume(Identifier, "Expect property name after '.'.");
+                expr = self.ast.add(ExprEnum::Get(Get { object: expr, name }));
+            } else {
+                break;
+            }
+        }
+
+        self.depth = depth;
+        expr
     }
 
-    fn primary(&mut self) -> Box<ExprEnum> {
+    fn subscript(&mut self, object: ExprId) -> ExprId {
+        let bracket = self.previous();
+        let start = if self.match_token(Colon) { None } else { Some(self.expression()) };
+        if !self.match_tokens(vec![Colon]) {
+            let end = self.peek().line;
+            self.consume(RightBracket, "Expect ']' after index.");
+            let index = start.unwrap_or_else(|| self.ast.add(ExprEnum::None));
+            return self.ast.add(ExprEnum::Index(Index { object, bracket, index, end }));
+        }
+        let stop = if self.match_token(RightBracket) { None } else { Some(self.expression()) };
+        let end = self.peek().line;
+        self.consume(RightBracket, "Expect ']' after slice.");
+        self.ast.add(ExprEnum::Slice(Slice { object, bracket, start, stop, end }))
+    }
+
+    fn finish_call(&mut self, callee: ExprId) -> ExprId {
+        let mut arguments = Vec::new();
+        if !self.match_token(RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    self.error(self.peek(), "Can't have more than 255 arguments.");
+                }
+                arguments.push(self.expression());
+                if !self.match_tokens(vec![Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self.peek();
+        self.consume(RightParen, "Expect ')' after arguments.");
+        self.ast.add(ExprEnum::Call(Call {
+            callee,
+            paren,
+            arguments,
+        }))
+    }
+
+    fn primary(&mut self) -> ExprId {
         if self.match_tokens(vec![False]) {
-            return Box::new(ExprEnum::Literal(Literal {
-                value: Box::from(false),
+            return self.ast.add(ExprEnum::Literal(Literal {
+                value: LiteralValue::Bool(false),
+                line: self.previous().line,
             }));
         }
         if self.match_tokens(vec![True]) {
-            return Box::new(ExprEnum::Literal(Literal {
-                value: Box::from(true),
+            return self.ast.add(ExprEnum::Literal(Literal {
+                value: LiteralValue::Bool(true),
+                line: self.previous().line,
             }));
         }
         if self.match_tokens(vec![Nil]) {
-            return Box::new(ExprEnum::Literal(Literal {
-                value: Box::new("nil"),
+            return self.ast.add(ExprEnum::Literal(Literal {
+                value: LiteralValue::Nil,
+                line: self.previous().line,
+            }));
+        }
+
+        if self.match_tokens(vec![Number]) {
+            let value = self.previous().lexeme.parse::<f64>().unwrap();
+            return self.ast.add(ExprEnum::Literal(Literal {
+                value: LiteralValue::Number(value),
+                line: self.previous().line,
             }));
         }
 
-        if self.match_tokens(vec![Number, TokenType::String]) {
-            return Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(self.previous().literal.unwrap()),
+        if self.match_tokens(vec![TokenType::String]) {
+            return self.ast.add(ExprEnum::Literal(Literal {
+                value: LiteralValue::String(self.previous().literal.unwrap()),
+                line: self.previous().line,
             }));
         }
 
         if self.match_tokens(vec![Identifier]) {
-            return Box::new(ExprEnum::Literal(Literal {
-                value: Box::new(self.previous().lexeme),
+            return self.ast.add(ExprEnum::Variable(Variable {
+                name: self.previous(),
             }));
         }
 
+        if self.match_tokens(vec![LeftBracket]) {
+            let bracket = self.previous();
+            let mut elements = Vec::new();
+            if !self.match_token(RightBracket) {
+                loop {
+                    elements.push(self.expression());
+                    if !self.match_tokens(vec![Comma]) {
+                        break;
+                    }
+                }
+            }
+            let end = self.peek().line;
+            self.consume(RightBracket, "Expect ']' after list elements.");
+            return self.ast.add(ExprEnum::List(List { bracket, elements, end }));
+        }
+
         if self.match_tokens(vec![LeftParen]) {
             let expr = self.expression();
             self.consume(TokenType::RightParen, "Expect ')' after expression.");
-            return Box::new(ExprEnum::Grouping(Grouping {
+            return self.ast.add(ExprEnum::Grouping(Grouping {
                 expression: expr,
             }));
         }
 
         self.error(self.peek(), "Expect expression.");
-        Box::new(ExprEnum::None)
+        self.ast.add(ExprEnum::None)
     }
 
     fn consume(&mut self, token_type: TokenType, err: &str) {
@@ -205,19 +401,18 @@ impl LoxParser {
     }
 
     fn error(&mut self, token: Token, msg: &str) {
+        if self.abandoned {
+            return;
+        }
         if token.token_type == TokenType::Eof {
-            report(token.line, " at end", msg);
+            self.errors.push(Diagnostic::new(token.line, " at end", msg));
         } else {
-            report(token.line, &format!(" at '{}'", token.lexeme), msg);
+            self.errors.push(Diagnostic::new(token.line, &format!(" at '{}'", token.lexeme), msg));
         }
         self.has_error = true;
     }
 }
 
-fn report(line: usize, location: &str, msg: &str) {
-    eprintln!("[line {}] Error{}: {}", line, location, msg);
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -237,9 +432,9 @@ mod tests {
         ];
 
         let mut parser = LoxParser::new(tokens);
-        let expr = parser.parse();
-        let ast_printer = crate::expr::AstPrinter {};
-        assert_eq!(expr.accept(&ast_printer), "(+ 1 (* 2 3))");
+        let ast = parser.parse();
+        let mut ast_printer = crate::expr::AstPrinter {};
+        assert_eq!(ast.accept(ast.root, &mut ast_printer), "(+ 1.0 (* 2.0 3.0))");
     }
 
     #[test]
@@ -266,7 +461,97 @@ mod tests {
         let _ = parser.parse();
         assert!(parser.has_error);
     }
-}
 
+    #[test]
+    fn test_trailing_tokens() {
+        let mut tokenizer = crate::lox_tokenizer::LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize("1 2 )))"));
+        parser.parse();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(parser.errors[0].to_string(), "[line 1] Error at '2': Expect end of expression.");
+
+        let mut parser = LoxParser::new(tokenizer.tokenize("(1))"));
+        parser.parse();
+        assert_eq!(parser.errors[0].to_string(), "[line 1] Error at ')': Expect end of expression.");
+    }
+
+    #[test]
+    fn test_call() {
+        let mut tokenizer = crate::lox_tokenizer::LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize("f(1)(a, -2)()"));
+        let ast = parser.parse();
+        assert_eq!(ast.accept(ast.root, &mut crate::expr::AstPrinter {}), "(call (call (call f 1.0) a (- 2.0)))");
+
+        let arguments = vec!["1"; 256].join(", ");
+        let mut parser = LoxParser::new(tokenizer.tokenize(&format!("f({})", arguments)));
+        parser.parse();
+        assert_eq!(parser.errors.len(), 1);
+        assert_eq!(parser.errors[0].message, "Can't have more than 255 arguments.");
+
+        let mut parser = LoxParser::new(tokenizer.tokenize("f(1"));
+        parser.parse();
+        assert_eq!(parser.errors[0].to_string(), "[line 1] Error at end: Expect ')' after arguments.");
+    }
 
+    #[test]
+    fn test_max_depth() {
+        let mut tokenizer = crate::lox_tokenizer::LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize("((1)) + --2"));
+        parser.max_depth = Some(3);
+        parser.parse();
+        assert!(!parser.has_error);
+        let mut parser = LoxParser::new(tokenizer.tokenize("a.b.c + f()()"));
+        parser.max_depth = Some(3);
+        parser.parse();
+        assert!(!parser.has_error);
+
+        for source in ["(((1)))", "1 + ---2", "f(((1)))", "a.b.c.d", "f()()[0]"] {
+            let mut parser = LoxParser::new(tokenizer.tokenize(source));
+            parser.max_depth = Some(3);
+            parser.parse();
+            assert_eq!(parser.errors.len(), 1, "{}", source);
+            assert_eq!(parser.errors[0].message, "Expression nesting exceeds the depth limit of 3.", "{}", source);
+        }
+    }
 
+    #[test]
+    fn test_lists() {
+        let mut tokenizer = crate::lox_tokenizer::LoxTokenizer::default();
+        let print = |source: &str| {
+            let mut parser = LoxParser::new(crate::lox_tokenizer::LoxTokenizer::default().tokenize(source));
+            let ast = parser.parse();
+            assert!(!parser.has_error, "{}", source);
+            ast.accept(ast.root, &mut crate::expr::AstPrinter {})
+        };
+        assert_eq!(print("[]"), "(list)");
+        assert_eq!(print("[1, [a], -2][0]"), "([] (list 1.0 (list a) (- 2.0)) 0.0)");
+        assert_eq!(print("a[1:-1] == a[:2][2:][:]"), "(== ([:] a 1.0 (- 1.0)) ([:] ([:] ([:] a nil 2.0) 2.0 nil) nil nil))");
+        assert_eq!(print("a[0] = b[1][2] = 3 + 4"), "([]= a 0.0 ([]= ([] b 1.0) 2.0 (+ 3.0 4.0)))");
+        assert_eq!(print("f(x)[0].y[1]"), "([] (. ([] (call f x) 0.0) y) 1.0)");
+
+        for (source, error) in [
+            ("[1, 2", "[line 1] Error at end: Expect ']' after list elements."),
+            ("a[1", "[line 1] Error at end: Expect ']' after index."),
+            ("a[1:2", "[line 1] Error at end: Expect ']' after slice."),
+            ("a[]", "[line 1] Error at ']': Expect expression."),
+            ("a = 1", "[line 1] Error at '=': Invalid assignment target."),
+            ("a[0:1] = 1", "[line 1] Error at '=': Invalid assignment target."),
+        ] {
+            let mut parser = LoxParser::new(tokenizer.tokenize(source));
+            parser.parse();
+            assert_eq!(parser.errors[0].to_string(), error, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_get() {
+        let mut tokenizer = crate::lox_tokenizer::LoxTokenizer::default();
+        let mut parser = LoxParser::new(tokenizer.tokenize("a.b.c(1).d"));
+        let ast = parser.parse();
+        assert_eq!(ast.accept(ast.root, &mut crate::expr::AstPrinter {}), "(. (call (. (. a b) c) 1.0) d)");
+
+        let mut parser = LoxParser::new(tokenizer.tokenize("a.1"));
+        parser.parse();
+        assert_eq!(parser.errors[0].to_string(), "[line 1] Error at '1': Expect property name after '.'.");
+    }
+}
@@ -0,0 +1,358 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::iter;
+use std::time::{Duration, Instant};
+use interpreter_starter_rust::expr::AstPrinter;
+use interpreter_starter_rust::token::Token;
+use interpreter_starter_rust::token_types::TokenType;
+use interpreter_starter_rust::{Interpreter, LoxError, LoxOptimizer, LoxTokenizer, Value};
+use crate::lox_cli::Reporter;
+
+const HISTORY_FILE: &str = ".lox_history";
+
+pub(crate) struct LoxRepl {
+    buffer: String,
+    history: Option<File>,
+    interpreter: Interpreter,
+    reporter: Reporter,
+    // Fold constant subexpressions of each entry before evaluating it
+    optimize: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Feed {
+    // More lines are needed before the entry can be parsed
+    Incomplete,
+    Output(String),
+    // The error was already printed to stderr
+    Error,
+}
+
+impl LoxRepl {
+    pub(crate) fn new(reporter: Reporter, interpreter: Interpreter, optimize: bool) -> Self {
+        // Piped sessions are scripts rather than typed input, so they leave no history
+        let history = if io::stdin().is_terminal() {
+            OpenOptions::new().create(true).append(true).open(HISTORY_FILE).ok()
+        } else {
+            None
+        };
+        LoxRepl {
+            buffer: String::new(),
+            history,
+            interpreter,
+            reporter,
+            optimize,
+        }
+    }
+
+    pub(crate) fn run(&mut self, input: impl Iterator<Item = io::Result<String>>, mut output: impl Write) -> io::Result<()> {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input {
+            let feed = self.feed(&line?);
+            match &feed {
+                Feed::Output(text) if !text.is_empty() => writeln!(output, "{}", text)?,
+                _ => {}
+            }
+            write!(output, "{}", if feed == Feed::Incomplete { "... " } else { "> " })?;
+            output.flush()?;
+        }
+        writeln!(output)
+    }
+
+    pub(crate) fn feed(&mut self, line: &str) -> Feed {
+        let line_is_command = line.trim_start().starts_with(':');
+        if line_is_command && (self.buffer.is_empty() || line.trim() == ":reset") {
+            self.record(line.trim());
+            return self.command(line.trim());
+        }
+
+        if !self.buffer.is_empty() {
+            self.buffer.push('\n');
+        }
+        self.buffer.push_str(line);
+        if self.buffer.trim().is_empty() {
+            self.buffer.clear();
+            return Feed::Output(String::new());
+        }
+
+        let mut tokenizer = LoxTokenizer::default();
+        let tokens = tokenizer.tokenize(&self.buffer);
+        if !tokenizer.had_error && open_delimiters(&tokens) > 0 {
+            return Feed::Incomplete;
+        }
+
+        let entry = std::mem::take(&mut self.buffer);
+        self.record(&entry);
+        self.execute(&entry)
+    }
+
+    fn command(&mut self, line: &str) -> Feed {
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (line, ""),
+        };
+        match name {
+            ":tokens" => {
+                let mut tokenizer = LoxTokenizer::default();
+                let tokens = tokenizer.tokenize(argument);
+                if tokenizer.had_error {
+                    self.reporter.report(&LoxError::Syntax(tokenizer.errors));
+                }
+                let lines: Vec<String> = tokens.iter().map(|token| token.to_string()).collect();
+                Feed::Output(lines.join("\n"))
+            }
+            ":ast" => match interpreter_starter_rust::parse(argument) {
+                Ok(ast) => Feed::Output(ast.accept(ast.root(), &mut AstPrinter {})),
+                Err(err) => self.fail(&err),
+            },
+            ":load" => match fs::read_to_string(argument) {
+                Ok(source) => self.execute(&source),
+                Err(err) => {
+                    eprintln!("Cannot read {}: {}", argument, err);
+                    Feed::Error
+                }
+            },
+            // Scripts cannot define globals, so pending input is the only session state to drop
+            ":reset" => {
+                self.buffer.clear();
+                Feed::Output(String::new())
+            }
+            ":env" => {
+                let lines: Vec<String> = self
+                    .interpreter
+                    .globals()
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                Feed::Output(lines.join("\n"))
+            }
+            ":type" => match self.evaluate(argument) {
+                Ok(value) => Feed::Output(value.type_name().to_string()),
+                Err(err) => self.fail(&err),
+            },
+            ":time" => {
+                // --deterministic sessions use the interpreter's virtual clock so they print the same
+                // time on every run; others a monotonic one, which changes to the system time can't skew
+                let deterministic = self.interpreter.is_deterministic();
+                let (start, virtual_start) = (Instant::now(), self.interpreter.clock());
+                match self.execute(argument) {
+                    Feed::Output(text) => {
+                        let elapsed = if deterministic {
+                            Duration::from_secs_f64((self.interpreter.clock() - virtual_start).max(0.0))
+                        } else {
+                            start.elapsed()
+                        };
+                        Feed::Output(format!("{}\n[{:?}]", text, elapsed))
+                    }
+                    feed => feed,
+                }
+            }
+            _ => {
+                eprintln!("Unknown command: {} (expected :tokens, :ast, :env, :type, :load, :reset or :time)", name);
+                Feed::Error
+            }
+        }
+    }
+
+    fn execute(&mut self, source: &str) -> Feed {
+        match self.evaluate(source) {
+            Ok(value) => Feed::Output(value.to_string()),
+            Err(err) => self.fail(&err),
+        }
+    }
+
+    fn evaluate(&mut self, source: &str) -> Result<Value, LoxError> {
+        let ast = self.interpreter.parse(source)?;
+        let ast = if self.optimize { LoxOptimizer::optimize(ast) } else { ast };
+        self.interpreter.evaluate(&ast)
+    }
+
+    fn fail(&self, err: &LoxError) -> Feed {
+        self.reporter.report(err);
+        Feed::Error
+    }
+
+    fn record(&mut self, entry: &str) {
+        if let Some(history) = &mut self.history {
+            let _ = writeln!(history, "{}", history_line(entry));
+        }
+    }
+}
+
+// One line per entry: backslashes and the newlines of multi-line entries are escaped
+fn history_line(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+// Lines of stdin without their line endings. Each read takes the stdin lock only for
+// that line, so readLine() in an entry reads the line after it
+pub(crate) fn stdin_lines() -> impl Iterator<Item = io::Result<String>> {
+    iter::from_fn(|| {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                Some(Ok(line.strip_suffix('\r').unwrap_or(line).to_string()))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    })
+}
+
+fn open_delimiters(tokens: &[Token]) -> i32 {
+    tokens.iter().fold(0, |depth, token| match token.token_type {
+        TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth + 1,
+        TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth - 1,
+        _ => depth,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use super::*;
+
+    fn repl() -> LoxRepl {
+        LoxRepl {
+            buffer: String::new(),
+            history: None,
+            interpreter: Interpreter::new(),
+            reporter: Reporter::default(),
+            optimize: false,
+        }
+    }
+
+    #[test]
+    fn test_feed_complete_expression() {
+        let mut repl = repl();
+        assert_eq!(repl.feed("1 + 2 * 3"), Feed::Output("7".to_string()));
+        assert_eq!(repl.feed("\"a\" + 1"), Feed::Error);
+    }
+
+    #[test]
+    fn test_feed_continues_unbalanced_input() {
+        let mut repl = repl();
+        assert_eq!(repl.feed("(1 +"), Feed::Incomplete);
+        assert_eq!(repl.feed("  (2"), Feed::Incomplete);
+        assert_eq!(repl.feed("))"), Feed::Output("3".to_string()));
+        assert_eq!(repl.feed("nil"), Feed::Output("nil".to_string()));
+        assert_eq!(repl.feed("[1,"), Feed::Incomplete);
+        assert_eq!(repl.feed("2][-1]"), Feed::Output("2".to_string()));
+    }
+
+    #[test]
+    fn test_feed_recovers_after_error() {
+        let mut repl = repl();
+        assert_eq!(repl.feed("1 +)"), Feed::Error);
+        assert_eq!(repl.feed("true"), Feed::Output("true".to_string()));
+        assert_eq!(repl.feed("@"), Feed::Error);
+    }
+
+    #[test]
+    fn test_run_prompts() {
+        let mut repl = repl();
+        let mut output = Vec::new();
+        repl.run("(1\n)\n".as_bytes().lines(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "> ... 1\n> \n");
+    }
+
+    #[test]
+    fn test_tokens_command() {
+        let mut repl = repl();
+        assert_eq!(
+            repl.feed(":tokens (foo"),
+            Feed::Output("LEFT_PAREN ( null\nIDENTIFIER foo null\nEOF  null".to_string())
+        );
+    }
+
+    #[test]
+    fn test_ast_command() {
+        let mut repl = repl();
+        assert_eq!(repl.feed(":ast -(1)"), Feed::Output("(- (group 1.0))".to_string()));
+        assert_eq!(repl.feed(":ast 1 +"), Feed::Error);
+    }
+
+    #[test]
+    fn test_load_command() {
+        let path = std::env::temp_dir().join(format!("lox_repl_load_{}.lox", std::process::id()));
+        fs::write(&path, "1 ==\n2").unwrap();
+        let mut repl = repl();
+        assert_eq!(repl.feed(&format!(":load {}", path.display())), Feed::Output("false".to_string()));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(repl.feed(&format!(":load {}", path.display())), Feed::Error);
+    }
+
+    #[test]
+    fn test_reset_command_drops_pending_input() {
+        let mut repl = repl();
+        assert_eq!(repl.feed("(1 +"), Feed::Incomplete);
+        assert_eq!(repl.feed(":reset"), Feed::Output(String::new()));
+        assert_eq!(repl.feed("2"), Feed::Output("2".to_string()));
+    }
+
+    #[test]
+    fn test_time_command() {
+        let mut repl = repl();
+        match repl.feed(":time 1 + 2") {
+            Feed::Output(text) => assert!(text.starts_with("3\n["), "{}", text),
+            feed => panic!("Expected output, got {:?}", feed),
+        }
+
+        repl.interpreter.define_native("nap", 0, |_| {
+            std::thread::sleep(Duration::from_millis(20));
+            Ok(Value::Nil)
+        });
+        match repl.feed(":time nap()") {
+            Feed::Output(text) => assert!(text.starts_with("nil\n[") && text.ends_with("ms]"), "{}", text),
+            feed => panic!("Expected output, got {:?}", feed),
+        }
+    }
+
+    #[test]
+    fn test_time_command_is_deterministic() {
+        let mut repl = repl();
+        repl.interpreter.set_deterministic(true);
+        assert_eq!(repl.feed(":time 1 + 2"), Feed::Output("3\n[3µs]".to_string()));
+    }
+
+    #[test]
+    fn test_env_command() {
+        let mut repl = repl();
+        repl.interpreter = Interpreter::default();
+        repl.interpreter.define("b", Value::from("text"));
+        repl.interpreter.define("a", Value::from(vec![1.0, 2.0]));
+        assert_eq!(repl.feed(":env"), Feed::Output("a = [1, 2]\nb = text".to_string()));
+    }
+
+    #[test]
+    fn test_type_command() {
+        let mut repl = repl();
+        assert_eq!(repl.feed(":type 1 + 2"), Feed::Output("number".to_string()));
+        assert_eq!(repl.feed(":type [nil]"), Feed::Output("list".to_string()));
+        assert_eq!(repl.feed(":type len"), Feed::Output("function".to_string()));
+        assert_eq!(repl.feed(":type -nil"), Feed::Error);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut repl = repl();
+        repl.optimize = true;
+        repl.interpreter.set_deterministic(true);
+        assert_eq!(repl.feed(":time (1 + 2) * 3"), Feed::Output("9\n[1µs]".to_string()));
+        assert_eq!(repl.feed("(1 + 2) *\nnil"), Feed::Error);
+    }
+
+    #[test]
+    fn test_history_line() {
+        assert_eq!(history_line("(1 +\n2)"), "(1 +\\n2)");
+        assert_eq!(history_line("\"a\\nb\""), "\"a\\\\nb\"");
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        let mut repl = repl();
+        assert_eq!(repl.feed(":vars"), Feed::Error);
+    }
+}
@@ -0,0 +1,276 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use crate::lox_capabilities::Capability;
+use crate::lox_interpreter::{Interpreter, Value};
+use crate::lox_list::LoxList;
+
+// Globals every Interpreter::new() starts with. Type errors name what was expected
+// and what was passed, and surface as runtime errors at the call
+pub(crate) fn define_core(interpreter: &mut Interpreter) {
+    interpreter.define_builtin("clock", 0, Some(Capability::Time), |interpreter, _| {
+        Ok(Value::Number(interpreter.clock()))
+    });
+
+    interpreter.define_native("len", 1, |args| match &args[0] {
+        Value::List(list) => Ok(list.len() as f64),
+        other => Ok(String::try_from(other)?.chars().count() as f64),
+    });
+    interpreter.define_native("str", 1, |args| Ok(args[0].to_string()));
+    interpreter.define_native("num", 1, |args| match &args[0] {
+        Value::Number(n) => Ok(*n),
+        Value::String(s) => Some(s.trim())
+            .filter(|text| is_number(text))
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| format!("Cannot convert '{}' to a number.", s)),
+        other => Err(format!("Cannot convert {} to a number.", other)),
+    });
+    interpreter.define_native("type", 1, |args| Ok(args[0].type_name()));
+
+    interpreter.define_native("sqrt", 1, |args| Ok(f64::try_from(&args[0])?.sqrt()));
+    interpreter.define_native("floor", 1, |args| Ok(f64::try_from(&args[0])?.floor()));
+    interpreter.define_native("abs", 1, |args| Ok(f64::try_from(&args[0])?.abs()));
+    interpreter.define_native("pow", 2, |args| Ok(f64::try_from(&args[0])?.powf(f64::try_from(&args[1])?)));
+    interpreter.define_native("min", 2, |args| Ok(f64::try_from(&args[0])?.min(f64::try_from(&args[1])?)));
+    interpreter.define_native("max", 2, |args| Ok(f64::try_from(&args[0])?.max(f64::try_from(&args[1])?)));
+
+    // Positions count characters, not bytes
+    interpreter.define_native("substr", 3, |args| {
+        let s = String::try_from(&args[0])?;
+        let (start, length) = (index(&args[1])?, index(&args[2])?);
+        let chars = s.chars().count();
+        // Huge arguments saturate rather than wrap, so they stay out of range
+        let end = start.saturating_add(length);
+        if end > chars {
+            return Err(format!("Substring {}..{} is out of range for a string of length {}.", start, end, chars));
+        }
+        Ok(s.chars().skip(start).take(length).collect::<String>())
+    });
+    interpreter.define_native("indexOf", 2, |args| {
+        let (s, part) = (String::try_from(&args[0])?, String::try_from(&args[1])?);
+        Ok(s.find(&part).map_or(-1.0, |byte| s[..byte].chars().count() as f64))
+    });
+    interpreter.define_native("upper", 1, |args| Ok(String::try_from(&args[0])?.to_uppercase()));
+    interpreter.define_native("lower", 1, |args| Ok(String::try_from(&args[0])?.to_lowercase()));
+
+    // An empty separator splits into characters
+    interpreter.define_native("split", 2, |args| {
+        let (s, separator) = (String::try_from(&args[0])?, String::try_from(&args[1])?);
+        if separator.is_empty() {
+            return Ok(Value::from(s.chars().map(String::from).collect::<Vec<_>>()));
+        }
+        Ok(Value::from(s.split(&separator).collect::<Vec<_>>()))
+    });
+    interpreter.define_native("join", 2, |args| {
+        let (list, separator) = (Rc::<LoxList>::try_from(&args[0])?, String::try_from(&args[1])?);
+        let items: Vec<String> = list.to_vec().iter().map(Value::to_string).collect();
+        Ok(items.join(&separator))
+    });
+}
+
+// File access goes through the read and write capabilities; errors carry the OS message
+pub(crate) fn define_io(interpreter: &mut Interpreter) {
+    let read = Some(Capability::Read);
+    let write = Some(Capability::Write);
+
+    interpreter.define_restricted_native("readFile", 1, read, |args| {
+        let path = String::try_from(&args[0])?;
+        fs::read_to_string(&path).map_err(|err| format!("Cannot read {}: {}", path, err))
+    });
+    interpreter.define_restricted_native("exists", 1, read, |args| Ok(Path::new(&String::try_from(&args[0])?).exists()));
+    // Names of the entries in a directory, sorted
+    interpreter.define_restricted_native("listDir", 1, read, |args| {
+        let path = String::try_from(&args[0])?;
+        let entries = fs::read_dir(&path).map_err(|err| format!("Cannot read {}: {}", path, err))?;
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| format!("Cannot read {}: {}", path, err))?;
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        Ok(Value::from(names))
+    });
+    interpreter.define_restricted_native("writeFile", 2, write, |args| {
+        let (path, contents) = (String::try_from(&args[0])?, String::try_from(&args[1])?);
+        fs::write(&path, contents).map_err(|err| format!("Cannot write {}: {}", path, err))
+    });
+    interpreter.define_restricted_native("appendFile", 2, write, |args| {
+        let (path, contents) = (String::try_from(&args[0])?, String::try_from(&args[1])?);
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(contents.as_bytes()))
+            .map_err(|err| format!("Cannot write {}: {}", path, err))
+    });
+
+    // A line from stdin without its line ending, or nil at the end of input
+    interpreter.define_native("readLine", 0, |_| {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) => Ok(Value::Nil),
+            Ok(_) => Ok(Value::String(line.trim_end_matches(['\n', '\r']).to_string())),
+            Err(err) => Err(format!("Cannot read stdin: {}", err)),
+        }
+    });
+    interpreter.define_native("eprint", 1, |args| {
+        eprintln!("{}", args[0]);
+        Ok(())
+    });
+}
+
+// A Lox number literal, `123` or `1.5`, optionally negated; Rust's parser would
+// also take "inf", "NaN" and "1e5"
+fn is_number(s: &str) -> bool {
+    let digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    let unsigned = s.strip_prefix('-').unwrap_or(s);
+    match unsigned.split_once('.') {
+        Some((whole, fraction)) => digits(whole) && digits(fraction),
+        None => digits(unsigned),
+    }
+}
+
+fn index(value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        other => Err(format!("Expected a non-negative integer but got {}.", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lox_capabilities::Capabilities;
+    use crate::lox_error::LoxError;
+    use super::*;
+
+    fn eval(source: &str) -> Result<Value, LoxError> {
+        Interpreter::new().eval(source)
+    }
+
+    fn string(s: &str) -> Result<Value, LoxError> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(eval("str(1.5) + str(nil) + str(2 > 1)"), string("1.5niltrue"));
+        assert_eq!(eval("num(\" 42 \") + num(0.5)"), Ok(Value::Number(42.5)));
+        assert_eq!(eval("num(\"4x\")"), Err(LoxError::runtime(1, "Cannot convert '4x' to a number.")));
+        assert_eq!(eval("num(\"-0.25\")"), Ok(Value::Number(-0.25)));
+        for text in ["NaN", "inf", "1e5", "1.", ".5", "+1", ""] {
+            assert_eq!(
+                eval(&format!("num(\"{}\")", text)),
+                Err(LoxError::runtime(1, &format!("Cannot convert '{}' to a number.", text)))
+            );
+        }
+        assert_eq!(eval("num(true)"), Err(LoxError::runtime(1, "Cannot convert true to a number.")));
+        assert_eq!(eval("type(1) + type(\"\") + type(nil) + type(!1) + type(type)"), string("numberstringnilbooleanfunction"));
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eq!(eval("sqrt(16) + floor(-1.5) + abs(-3)"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("pow(2, 10) - max(3, min(7, 5))"), Ok(Value::Number(1019.0)));
+        assert_eq!(eval("sqrt(\"4\")"), Err(LoxError::runtime(1, "Expected a number but got 4.")));
+        assert_eq!(eval("pow(2)"), Err(LoxError::runtime(1, "Expected 2 arguments but got 1.")));
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(eval("len(\"héllo\")"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("substr(\"héllo\", 1, 3)"), string("éll"));
+        assert_eq!(eval("indexOf(\"héllo\", \"l\") + indexOf(\"héllo\", \"x\")"), Ok(Value::Number(1.0)));
+        assert_eq!(eval("substr(\"abc\", 3, 0)"), string(""));
+        assert_eq!(eval("upper(\"abc\") + lower(\"DEF\")"), string("ABCdef"));
+
+        assert_eq!(
+            eval("substr(\"abc\", 2, 2)"),
+            Err(LoxError::runtime(1, "Substring 2..4 is out of range for a string of length 3."))
+        );
+        assert_eq!(
+            eval("substr(\"abc\", 1, 18446744073709551615)"),
+            Err(LoxError::runtime(1, "Substring 1..18446744073709551615 is out of range for a string of length 3."))
+        );
+        assert_eq!(eval("substr(\"abc\", 0.5, 1)"), Err(LoxError::runtime(1, "Expected a non-negative integer but got 0.5.")));
+        assert_eq!(eval("len(1)"), Err(LoxError::runtime(1, "Expected a string but got 1.")));
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(eval("len([1, 2, 3]) + len(\"ab\")"), Ok(Value::Number(5.0)));
+        assert_eq!(eval("type([])"), string("list"));
+        assert_eq!(eval("split(\"a,b,,c\", \",\")").unwrap().to_string(), "[\"a\", \"b\", \"\", \"c\"]");
+        assert_eq!(eval("split(\"abc\", \"\")[1:]").unwrap().to_string(), "[\"b\", \"c\"]");
+        assert_eq!(eval("join([1, \"a\", nil], \"-\")"), string("1-a-nil"));
+        assert_eq!(eval("join(split(\"a b\", \" \"), \"\")"), string("ab"));
+        assert_eq!(eval("join(\"ab\", \"\")"), Err(LoxError::runtime(1, "Expected a list but got ab.")));
+    }
+
+    fn io_interpreter(dir: &Path) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_capabilities(Capabilities {
+            read: vec![dir.to_path_buf()],
+            write: true,
+            ..Capabilities::default()
+        });
+        interpreter.define("dir", Value::from(dir.display().to_string() + "/"));
+        interpreter
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("lox_stdlib_files_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut interpreter = io_interpreter(&dir);
+
+        assert_eq!(interpreter.eval("exists(dir + \"a.txt\")"), Ok(Value::Bool(false)));
+        assert_eq!(interpreter.eval("writeFile(dir + \"a.txt\", \"one\")"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("appendFile(dir + \"a.txt\", \" two\")"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("appendFile(dir + \"b.txt\", \"new\")"), Ok(Value::Nil));
+        assert_eq!(interpreter.eval("readFile(dir + \"a.txt\") + readFile(dir + \"b.txt\")"), string("one twonew"));
+        assert_eq!(interpreter.eval("exists(dir + \"a.txt\")"), Ok(Value::Bool(true)));
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), "one two");
+        assert_eq!(interpreter.eval("listDir(dir)").unwrap().to_string(), "[\"a.txt\", \"b.txt\"]");
+
+        let missing = dir.join("missing.txt").display().to_string();
+        match interpreter.eval("readFile(dir + \"missing.txt\")") {
+            Err(LoxError::Runtime { message, .. }) => assert!(message.starts_with(&format!("Cannot read {}: ", missing)), "{}", message),
+            other => panic!("Expected a runtime error, got {:?}", other),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_files_need_capabilities() {
+        let dir = std::env::temp_dir().join(format!("lox_stdlib_denied_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.define("dir", Value::from(dir.display().to_string() + "/"));
+
+        assert_eq!(interpreter.eval("writeFile(dir + \"a.txt\", \"x\")"), Err(LoxError::runtime(1, "writeFile() needs the 'write' capability.")));
+        assert!(!dir.join("a.txt").exists());
+        assert_eq!(
+            interpreter.eval("exists(dir)"),
+            Err(LoxError::runtime(1, &format!("exists() needs the 'read' capability for {}/.", dir.display())))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_clock_needs_the_time_capability() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval("clock()"), Err(LoxError::runtime(1, "clock() needs the 'time' capability.")));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_capabilities(Capabilities { time: true, ..Capabilities::default() });
+        assert!(interpreter.eval("clock()").is_ok());
+
+        // The virtual clock is granted without the capability
+        let mut interpreter = Interpreter::new();
+        interpreter.set_deterministic(true);
+        // The call and the variable holding clock are evaluated before it reads the time
+        assert_eq!(interpreter.eval("clock()"), Ok(Value::Number(0.000002)));
+        assert_eq!(interpreter.eval("clock() < clock()"), Ok(Value::Bool(true)));
+    }
+}
@@ -1,16 +1,21 @@
+use crate::lox_error::Diagnostic;
 use crate::token::Token;
 use crate::token_types::TokenType;
-use std::io::Write;
-use std::io;
-use unicode_segmentation::UnicodeSegmentation;
 
+#[derive(Default)]
 pub struct LoxTokenizer {
-    pub(crate) had_error: bool,
+    pub had_error: bool,
+    pub errors: Vec<Diagnostic>,
 }
 
 impl LoxTokenizer {
-    pub(crate) fn tokenize(&mut self, input: &str) -> Vec<Token>{
-        tokenize(self, &input)
+    pub fn tokenize(&mut self, input: &str) -> Vec<Token>{
+        tokenize(self, input)
+    }
+
+    fn error(&mut self, line: usize, msg: &str) {
+        self.errors.push(Diagnostic::new(line, "", msg));
+        self.had_error = true;
     }
 }
 
@@ -19,9 +24,11 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
     let mut line = 1;
     let mut current = 0;
 
-    let len = input.graphemes(true).count();
+    // Positions index characters, never bytes, so non-ASCII text is sliced correctly
+    let chars: Vec<char> = input.chars().collect();
+    let len = chars.len();
     while current < len {
-        let c = input.chars().nth(current).unwrap();
+        let c = chars[current];
         match c {
             '\n' => {
                 line += 1;
@@ -38,6 +45,15 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
             '}' => {
                 tokens.push(Token::new(TokenType::RightBrace, "}".to_string(), None, line));
             }
+            '[' => {
+                tokens.push(Token::new(TokenType::LeftBracket, "[".to_string(), None, line));
+            }
+            ']' => {
+                tokens.push(Token::new(TokenType::RightBracket, "]".to_string(), None, line));
+            }
+            ':' => {
+                tokens.push(Token::new(TokenType::Colon, ":".to_string(), None, line));
+            }
             ',' => {
                 tokens.push(Token::new(TokenType::Comma, ",".to_string(), None, line));
             }
@@ -57,7 +73,7 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 tokens.push(Token::new(TokenType::Star, "*".to_string(), None, line));
             }
             '!' => {
-                if current < len - 1 && input.chars().nth(current + 1).unwrap() == '=' {
+                if current < len - 1 && chars[current + 1] == '=' {
                     current += 1;
                     tokens.push(Token::new(TokenType::BangEqual, "!=".to_string(), None, line));
                 } else {
@@ -65,7 +81,7 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 };
             }
             '=' => {
-                if current < len - 1 && input.chars().nth(current + 1).unwrap() == '=' {
+                if current < len - 1 && chars[current + 1] == '=' {
                     current += 1;
                     tokens.push(Token::new(
                         TokenType::EqualEqual,
@@ -78,7 +94,7 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 };
             }
             '<' => {
-                if current < len - 1 && input.chars().nth(current + 1).unwrap() == '=' {
+                if current < len - 1 && chars[current + 1] == '=' {
                     current += 1;
                     tokens.push(Token::new(TokenType::LessEqual, "<=".to_string(), None, line));
                 } else {
@@ -86,7 +102,7 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 };
             }
             '>' => {
-                if current < len - 1 && input.chars().nth(current + 1).unwrap() == '=' {
+                if current < len - 1 && chars[current + 1] == '=' {
                     current += 1;
                     tokens.push(Token::new(TokenType::GreaterEqual, ">=".to_string(), None, line));
                 } else {
@@ -94,9 +110,9 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 };
             }
             '/' => {
-                if current < len - 1 && input.chars().nth(current + 1).unwrap() == '/' {
+                if current < len - 1 && chars[current + 1] == '/' {
                     current += 1;
-                    while current < len && input.chars().nth(current).unwrap() != '\n' {
+                    while current < len && chars[current] != '\n' {
                         current += 1;
                     }
                     if current < len {
@@ -111,18 +127,17 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
             }
             '"' => {
                 let start = current + 1;
-                while current < len - 1 && input.chars().nth(current + 1).unwrap() != '"' {
+                while current < len - 1 && chars[current + 1] != '"' {
                     current += 1;
-                    if input.chars().nth(current).unwrap() == '\n' {
+                    if chars[current] == '\n' {
                         line += 1;
                     }
                 }
 
-                if current == len - 1 || input.chars().nth(current + 1).unwrap() != '"' {
-                    writeln!(io::stderr(), "[line {}] Error: Unterminated string.", line).unwrap();
-                    lox.had_error = true;
+                if current == len - 1 || chars[current + 1] != '"' {
+                    lox.error(line, "Unterminated string.");
                 } else {
-                    let value = input[start..current + 1].to_string();
+                    let value: String = chars[start..current + 1].iter().collect();
                     tokens.push(Token::new(
                         TokenType::String,
                         format!("\"{}\"", value),
@@ -134,16 +149,16 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
             }
             '0'..='9' => {
                 let start = current;
-                while current < len && input.chars().nth(current).unwrap().is_numeric() {
+                while current < len && chars[current].is_ascii_digit() {
                     current += 1;
                 }
-                if current < len && input.chars().nth(current).unwrap() == '.' {
+                if current < len && chars[current] == '.' {
                     current += 1;
-                    while current < len && input.chars().nth(current).unwrap().is_numeric() {
+                    while current < len && chars[current].is_ascii_digit() {
                         current += 1;
                     }
                 }
-                let mut value = input[start..current].to_string();
+                let mut value: String = chars[start..current].iter().collect();
                 let literal = value.clone();
                 let literal = literal.parse::<f32>().unwrap();
                 if value.ends_with(".") {
@@ -157,13 +172,14 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
             'a'..='z' | 'A'..='Z' | '_' => {
                 let mut end = current;
                 while end < len {
-                    let ch = input.chars().nth(end).unwrap();
+                    let ch = chars[end];
                     if !ch.is_alphanumeric() && ch != '_' {
                         break;
                     }
                     end += 1;
                 }
-                let identifier = &input[current..end];
+                let identifier: String = chars[current..end].iter().collect();
+                let identifier = identifier.as_str();
 
                 match identifier {
                     "and" => tokens.push(Token::new(TokenType::And, identifier.to_string(), None, line)),
@@ -187,8 +203,7 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
                 current = end - 1;
             }
             _ => {
-                writeln!(io::stderr(), "[line {}] Error: Unexpected character: {}", line, c).unwrap();
-                lox.had_error = true;
+                lox.error(line, &format!("Unexpected character: {}", c));
             }
         }
         current += 1;
@@ -197,14 +212,9 @@ fn tokenize(lox: &mut LoxTokenizer, input: &str) -> Vec<Token> {
     tokens
 }
 
-impl Default for LoxTokenizer {
-    fn default() -> Self {
-        LoxTokenizer { had_error: false }
-    }
-}
-
 // test
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -229,6 +239,20 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_brackets_and_colon() {
+        let mut lox = LoxTokenizer::default();
+        let result = tokenize(&mut lox, "[:]");
+        let expected = vec![
+            Token::new(TokenType::LeftBracket, "[".to_string(), None, 1),
+            Token::new(TokenType::Colon, ":".to_string(), None, 1),
+            Token::new(TokenType::RightBracket, "]".to_string(), None, 1),
+            Token::new(TokenType::Eof, "".to_string(), None, 1),
+        ];
+        assert_eq!(result, expected);
+        assert_eq!(lox.had_error, false);
+    }
+
     #[test]
     fn test_bang() {
         let mut lox = LoxTokenizer::default();
@@ -377,6 +401,25 @@ mod tests {
         assert_eq!(lox.had_error, false);
     }
 
+    #[test]
+    fn test_non_ascii_text() {
+        let mut lox = LoxTokenizer::default();
+        let input = "\"héllo wörld\" naïve";
+        let result = tokenize(&mut lox, input);
+        let expected = vec![
+            Token::new(
+                TokenType::String,
+                "\"héllo wörld\"".to_string(),
+                Some(String::from("héllo wörld")),
+                1,
+            ),
+            Token::new(TokenType::Identifier, "naïve".to_string(), None, 1),
+            Token::new(TokenType::Eof, "".to_string(), None, 1),
+        ];
+        assert_eq!(result, expected);
+        assert_eq!(lox.had_error, false);
+    }
+
     #[test]
     fn test_unterminated_string() {
         let mut lox = LoxTokenizer::default();
@@ -1,75 +1,122 @@
 use std::{env, process};
-use std::fs;
-use std::io::{self, Write};
-use crate::expr::AstPrinter;
-use crate::lox_tokenizer::LoxTokenizer;
+use std::io;
+use interpreter_starter_rust::expr::{AstPrinter, DotPrinter, JsonPrinter};
+use interpreter_starter_rust::{Interpreter, LoxError, LoxOptimizer, LoxTokenizer};
+use crate::lox_cli::{Command, LoxCli, Reporter, EXIT_DATA, EXIT_INPUT, EXIT_RUNTIME, EXIT_USAGE};
+use crate::lox_repl::LoxRepl;
 
-mod token_types;
-mod token;
-mod lox_tokenizer;
-mod expr;
-mod lox_parser;
+mod lox_repl;
+mod lox_cli;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        writeln!(io::stderr(), "Usage: {} tokenize <filename>", args[0]).unwrap();
-        return;
-    }
-
-    let command = &args[1];
-    let filename = &args[2];
+    let program = args.first().map(String::as_str).unwrap_or("lox");
+    let cli = LoxCli::parse(&args[1.min(args.len())..]).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        eprintln!("Run '{} help' for usage.", program);
+        process::exit(EXIT_USAGE);
+    });
+    let reporter = Reporter::new(cli.color, cli.diagnostics);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_limits(cli.limits);
+    interpreter.set_capabilities(cli.capabilities.clone());
+    interpreter.set_deterministic(cli.deterministic);
 
-    match command.as_str() {
-        "tokenize" => {
-            let file_contents = read_file(filename);
-            if !file_contents.is_empty() {
-                writeln!(io::stderr(), "Read file with content: {}", file_contents).unwrap();
-                let mut tokenizer = LoxTokenizer::default();
-                let result = tokenizer.tokenize(&file_contents);
-                for token in result {
-                     writeln!(io::stdout(), "{}", token).unwrap();
-                }
-                if tokenizer.had_error {
-                    process::exit(65)
+    let status = match &cli.command {
+        Command::Help(topic) => match lox_cli::help(program, topic.as_deref()) {
+            Ok(text) => {
+                println!("{}", text);
+                0
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                EXIT_USAGE
+            }
+        },
+        Command::Repl => match LoxRepl::new(reporter, interpreter, cli.optimize).run(lox_repl::stdin_lines(), io::stdout()) {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("{}", err);
+                EXIT_INPUT
+            }
+        },
+        Command::Tokenize | Command::Parse | Command::Evaluate => {
+            // Every source is processed; the first failure decides the exit code
+            let mut status = 0;
+            for source in &cli.sources {
+                let code = match source.read() {
+                    Ok(text) => run(&cli, &reporter, &mut interpreter, &text),
+                    Err(err) => {
+                        eprintln!("Failed to read file {}: {}", source.name(), err);
+                        EXIT_INPUT
+                    }
                 };
-            } else {
-                println!("EOF  null"); // Placeholder, remove this line when implementing the scanner
+                if status == 0 {
+                    status = code;
+                }
             }
+            status
         }
-        "parse" => {
-            let file_contents = read_file(filename);
+    };
+    process::exit(status);
+}
 
-            if !file_contents.is_empty() {
-                writeln!(io::stderr(), "Read file with content: {}", file_contents).unwrap();
-                let mut lox_tokenizer = LoxTokenizer::default();
-                let tokens = lox_tokenizer.tokenize(&file_contents);
-                if lox_tokenizer.had_error {
-                    process::exit(65)
-                }
-                for token in tokens.clone() {
-                    writeln!(io::stderr(), "{}", token).unwrap();
-                }
-                let mut parser = lox_parser::LoxParser::new(tokens);
-                let expr = parser.parse();
-                if parser.has_error {
-                    process::exit(65);
-                }
-                println!("{}", expr.accept(&AstPrinter {}));
-            } else {
-                eprintln!("Cannot read from the file");
-            }
+fn run(cli: &LoxCli, reporter: &Reporter, interpreter: &mut Interpreter, source: &str) -> i32 {
+    if cli.verbose {
+        eprintln!("Read file with content: {}", source);
+    }
+
+    if cli.command == Command::Tokenize {
+        let mut tokenizer = LoxTokenizer::default();
+        for token in tokenizer.tokenize(source) {
+            println!("{}", token);
+        }
+        if tokenizer.had_error {
+            reporter.report(&LoxError::Syntax(tokenizer.errors));
+            return EXIT_DATA;
         }
-        _ => {
-            writeln!(io::stderr(), "Unknown command: {}", command).unwrap();
-            return;
+        return 0;
+    }
+    if cli.verbose {
+        if let Ok(tokens) = interpreter_starter_rust::tokenize(source) {
+            for token in tokens.iter() {
+                eprintln!("{}", token);
+            }
         }
     }
+
+    if cli.command == Command::Evaluate {
+        let result = interpreter.parse(source).and_then(|ast| {
+            let ast = if cli.optimize { LoxOptimizer::optimize(ast) } else { ast };
+            interpreter.evaluate(&ast)
+        });
+        return report(reporter, result.map(|value| value.to_string()));
+    }
+
+    let result = interpreter_starter_rust::parse(source).map(|ast| {
+        let ast = if cli.optimized { LoxOptimizer::optimize(ast) } else { ast };
+        match cli.format.as_str() {
+            "json" => ast.accept(ast.root(), &mut JsonPrinter {}),
+            "dot" => DotPrinter::default().print(&ast),
+            _ => ast.accept(ast.root(), &mut AstPrinter {}),
+        }
+    });
+    report(reporter, result)
 }
 
-fn read_file(filename: &String) -> String {
-    fs::read_to_string(filename).unwrap_or_else(|_| {
-        writeln!(io::stderr(), "Failed to read file {}", filename).unwrap();
-        String::new()
-    })
+// Prints the output, or reports the error and returns its exit code
+fn report(reporter: &Reporter, result: Result<String, LoxError>) -> i32 {
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(err) => {
+            reporter.report(&err);
+            match err {
+                LoxError::Syntax(_) => EXIT_DATA,
+                _ => EXIT_RUNTIME,
+            }
+        }
+    }
 }
@@ -4,10 +4,10 @@ use crate::token_types::TokenType;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
-    pub(crate) token_type: TokenType,
-    pub(crate) lexeme: String,
-    pub(crate) literal: Option<String>,
-    pub(crate) line: usize,
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub literal: Option<String>,
+    pub line: usize,
 }
 
 impl fmt::Display for Token {
@@ -7,6 +7,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -58,6 +61,9 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => write!(f, "RIGHT_PAREN"),
             TokenType::LeftBrace => write!(f, "LEFT_BRACE"),
             TokenType::RightBrace => write!(f, "RIGHT_BRACE"),
+            TokenType::LeftBracket => write!(f, "LEFT_BRACKET"),
+            TokenType::RightBracket => write!(f, "RIGHT_BRACKET"),
+            TokenType::Colon => write!(f, "COLON"),
             TokenType::Comma => write!(f, "COMMA"),
             TokenType::Dot => write!(f, "DOT"),
             TokenType::Minus => write!(f, "MINUS"),
@@ -0,0 +1,281 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn lox(args: &[&str]) -> Output {
+    lox_with_stdin(args, "")
+}
+
+fn lox_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter-starter-rust"))
+        .args(args)
+        .current_dir(std::env::temp_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start the interpreter");
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("lox_cli_{}_{}", std::process::id(), name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_tokenize_file() {
+    let path = temp_file("tokenize.lox", "(1)");
+    let output = lox(&["tokenize", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "LEFT_PAREN ( null\nNUMBER 1 1.0\nRIGHT_PAREN ) null\nEOF  null\n");
+    assert_eq!(stderr(&output), "");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_parse_multiple_sources_in_order() {
+    let path = temp_file("parse.lox", "1 + 2");
+    let output = lox_with_stdin(&["parse", path.to_str().unwrap(), "-", "-e", "!x"], "(nil)");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "(+ 1.0 2.0)\n(group nil)\n(! x)\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_parse_formats() {
+    let output = lox(&["parse", "--format=json", "--optimized", "-e", "(1 + 2) * x"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).starts_with(r#"{"kind":"binary","span":{"start":1,"end":1},"operator":"*""#));
+
+    let output = lox(&["parse", "--format=dot", "-e", "x"]);
+    assert_eq!(stdout(&output), "digraph ast {\n    n0 [label=\"x\"];\n}\n");
+}
+
+#[test]
+fn test_parse_long_chains() {
+    let path = temp_file("long_chain.lox", &vec!["x"; 20_000].join(" + "));
+    for format in ["--format=sexpr", "--format=json", "--format=dot"] {
+        let output = lox(&["parse", format, path.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(0), "{}", format);
+    }
+    fs::remove_file(path).unwrap();
+
+    let path = temp_file("long_postfix.lox", &format!("x{}", ".y".repeat(20_000)));
+    let output = lox(&["parse", "--format=json", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), "[line 1] Error at '.': Expression nesting exceeds the depth limit of 128.\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_verbose_echoes_source() {
+    let output = lox(&["--verbose", "tokenize", "-e", "x"]);
+    assert_eq!(stderr(&output), "Read file with content: x\n");
+    assert_eq!(lox(&["tokenize", "-e", "x"]).stderr, b"");
+}
+
+#[test]
+fn test_exit_code_for_invalid_source() {
+    let output = lox(&["tokenize", "-e", "@"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), "[line 1] Error: Unexpected character: @\n");
+
+    let output = lox(&["parse", "-e", "(1"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), "[line 1] Error at end: Expect ')' after expression.\n");
+
+    let output = lox(&["evaluate", "-e", "1 2 )))"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stdout(&output), "");
+    assert_eq!(stderr(&output), "[line 1] Error at '2': Expect end of expression.\n");
+}
+
+#[test]
+fn test_evaluate() {
+    let output = lox_with_stdin(&["evaluate", "-e", "(1 + 2) * 3", "-"], "\"a\" + \"b\"");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "9\nab\n");
+
+    let output = lox(&["evaluate", "-e", "-\"a\"", "-e", "1 +"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Operand must be a number.\n[line 1]\n[line 1] Error at end: Expect expression.\n");
+}
+
+#[test]
+fn test_evaluate_lists() {
+    let output = lox(&["evaluate", "-e", "[1, \"a\", [nil]][1:]", "-e", "[1][1]"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "[\"a\", [nil]]\n");
+    assert_eq!(stderr(&output), "Index 1 is out of range for a list of length 1.\n[line 1]\n");
+}
+
+#[test]
+fn test_evaluate_limits() {
+    let output = lox(&["evaluate", "--max-steps=3", "-e", "1 + 2", "-e", "1 + 2 + 3"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stdout(&output), "3\n");
+    assert_eq!(stderr(&output), "Step limit of 3 exceeded.\n[line 1]\n");
+
+    let output = lox(&["evaluate", "--diagnostics=json", "--max-memory=2", "-e", "\"a\" + \"bc\""]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "{\"line\":1,\"message\":\"Memory limit of 2 bytes exceeded.\"}\n");
+}
+
+#[test]
+fn test_evaluate_max_depth_applies_to_parsing() {
+    let path = temp_file("nested.lox", &format!("{}1{}", "(".repeat(2000), ")".repeat(2000)));
+    let output = lox(&["evaluate", "--max-depth=100", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), "[line 1] Error at '(': Expression nesting exceeds the depth limit of 100.\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_evaluate_has_a_default_depth_limit() {
+    let path = temp_file("nested.lox", &format!("{}1{}", "(".repeat(200), ")".repeat(200)));
+    let output = lox(&["evaluate", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(stderr(&output), "[line 1] Error at '(': Expression nesting exceeds the depth limit of 128.\n");
+
+    let output = lox(&["evaluate", "--max-depth=300", path.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "1\n");
+    fs::remove_file(path).unwrap();
+
+    // A flat chain is one level however long it is
+    let path = temp_file("chain.lox", &vec!["1"; 1000].join(" + "));
+    let output = lox(&["evaluate", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "1000\n");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_evaluate_optimize() {
+    let output = lox(&["evaluate", "--optimize", "--max-steps=1", "-e", "(1 + 2) * 3"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "9\n");
+
+    // Folding keeps the operator's line, so runtime errors still point at it
+    let output = lox(&["evaluate", "--optimize", "-e", "(1 + 2)\n* -\"a\""]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(stderr(&output), "Operand must be a number.\n[line 2]\n");
+    let output = lox(&["evaluate", "--optimize", "-e", "(1 + 2)\n*\n\"a\""]);
+    assert_eq!(stderr(&output), "Operands must be numbers.\n[line 2]\n");
+}
+
+#[test]
+fn test_console_io() {
+    let output = lox_with_stdin(&["evaluate", "-e", "upper(readLine()) + str(eprint(readLine()))", "-e", "readLine()"], "first\r\nsecond\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "FIRSTnil\nnil\n");
+    assert_eq!(stderr(&output), "second\n");
+}
+
+#[test]
+fn test_read_capability_allows_missing_relative_paths() {
+    let name = format!("lox_cli_{}_missing.txt", std::process::id());
+    let output = lox(&["evaluate", "--allow-read=.", "-e", &format!("exists(\"{}\")", name)]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "false\n");
+    let output = lox(&["evaluate", "--allow-read=.", "-e", &format!("readFile(\"{}\")", name)]);
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).starts_with(&format!("Cannot read {}: No such file", name)));
+}
+
+#[test]
+fn test_repl_read_line() {
+    let output = lox_with_stdin(&["repl"], "upper(readLine())\nhello\nreadLine()\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "> HELLO\n> nil\n> \n");
+}
+
+#[test]
+fn test_exit_code_for_unreadable_input() {
+    let output = lox(&["parse", "-e", "1", "does-not-exist.lox", "-e", "("]);
+    assert_eq!(output.status.code(), Some(66));
+    assert_eq!(stdout(&output), "1.0\n");
+    assert!(stderr(&output).starts_with("Failed to read file does-not-exist.lox:"));
+}
+
+#[test]
+fn test_exit_code_for_usage_errors() {
+    for args in [&["run", "a.lox"][..], &["parse"], &["parse", "--format=xml", "-e", "1"], &["tokenize", "--optimized", "-e", "1"]] {
+        let output = lox(args);
+        assert_eq!(output.status.code(), Some(64), "{:?}", args);
+        assert!(stderr(&output).contains("help' for usage."), "{:?}", args);
+    }
+}
+
+#[test]
+fn test_help() {
+    let output = lox(&["--help"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout(&output).contains("Commands:"));
+
+    let output = lox(&["parse", "-h"]);
+    assert!(stdout(&output).contains("--format=<format>"));
+
+    let output = lox(&["help", "repl"]);
+    assert!(stdout(&output).contains(":tokens <code>"));
+
+    assert_eq!(lox(&["help", "run"]).status.code(), Some(64));
+}
+
+#[test]
+fn test_diagnostics_and_color() {
+    let output = lox(&["parse", "--diagnostics=json", "-e", "1 +"]);
+    assert_eq!(stderr(&output), "{\"line\":1,\"location\":\"at end\",\"message\":\"Expect expression.\"}\n");
+
+    let output = lox(&["parse", "--color=always", "-e", "1 +"]);
+    assert_eq!(stderr(&output), "[line 1] \x1b[1;31mError\x1b[0m at end: Expect expression.\n");
+
+    // stderr is a pipe here, so auto means no color
+    let output = lox(&["parse", "-e", "1 +"]);
+    assert_eq!(stderr(&output), "[line 1] Error at end: Expect expression.\n");
+}
+
+#[test]
+fn test_deterministic_repl_timing() {
+    let runs: Vec<String> = (0..2).map(|_| stdout(&lox_with_stdin(&["--deterministic"], ":time 1 + 2 * 3\n"))).collect();
+    assert_eq!(runs[0], "> 7\n[5µs]\n> \n");
+    assert_eq!(runs[0], runs[1]);
+}
+
+#[test]
+fn test_deterministic_clock_needs_no_capability() {
+    let output = lox(&["evaluate", "--deterministic", "-e", "clock()"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "0.000002\n");
+
+    let output = lox(&["evaluate", "-e", "clock()"]);
+    assert_eq!(output.status.code(), Some(70));
+}
+
+#[test]
+fn test_repl_without_arguments() {
+    let output = lox_with_stdin(&[], "1 +\n2\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "> > 2\n> \n");
+    assert_eq!(stderr(&output), "[line 1] Error at end: Expect expression.\n");
+}
+
+#[test]
+fn test_piped_repl_keeps_no_history() {
+    let history = std::env::temp_dir().join(".lox_history");
+    let _ = fs::remove_file(&history);
+    let output = lox_with_stdin(&["repl"], "1\n");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!history.exists());
+}